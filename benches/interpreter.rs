@@ -0,0 +1,60 @@
+// Measures how many instructions per second the interpreter runs, with and
+// without the decode cache, on a synthetic loop and on a real game. Run with
+// `cargo bench`.
+extern crate chip8;
+
+use chip8::Chip8;
+use std::hint::black_box;
+use std::time::Instant;
+
+const INSTRUCTIONS: u32 = 20_000_000;
+// The delay timer counts down at 60 Hz of a 500 Hz game, so that games
+// waiting on it get on with the rest of their loop.
+const TICKS_PER_TIMER: u32 = 8;
+
+// A loop of arithmetic, I updates and skips with a draw now and then, the
+// mix most games spend their time in:
+//
+//     200: LD V0, 0
+//     202: ADD V0, 1
+//     204: ADD V1, V0
+//     206: XOR V2, V1
+//     208: LD I, 0x300
+//     20A: ADD I, V0
+//     20C: SNE V0, 0xFF
+//     20E: DRW V1, V2, 1
+//     210: SE V0, 0xFF
+//     212: JP 0x202
+//     214: JP 0x200
+const LOOP: [u8; 22] = [
+    0x60, 0x00, 0x70, 0x01, 0x81, 0x04, 0x82, 0x13, 0xA3, 0x00, 0xF0, 0x1E, 0x40, 0xFF, 0xD1, 0x21, 0x30, 0xFF, 0x12, 0x02, 0x12, 0x00,
+];
+// Breakout, playing its attract mode without input.
+const BRIX: &[u8] = include_bytes!("../games/BRIX");
+
+fn run(rom: &[u8], cache: bool) -> f64 {
+    let mut chip8 = Chip8::new(rom).unwrap();
+    chip8.set_decode_cache(cache);
+    let start = Instant::now();
+    for tick in 0..INSTRUCTIONS {
+        black_box(chip8.tick());
+        if tick % TICKS_PER_TIMER == 0 {
+            chip8.decrement_counter();
+        }
+    }
+    f64::from(INSTRUCTIONS) / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    for &(name, rom) in &[("loop", &LOOP[..]), ("BRIX", BRIX)] {
+        // Warm up before measuring either.
+        run(rom, true);
+
+        let uncached = run(rom, false);
+        let cached = run(rom, true);
+        println!("{}", name);
+        println!("  without decode cache: {:12.0} instructions per second", uncached);
+        println!("  with decode cache:    {:12.0} instructions per second", cached);
+        println!("  speedup:              {:12.2}x", cached / uncached);
+    }
+}
@@ -0,0 +1,217 @@
+use chip8::coverage::Coverage;
+use chip8::profiler::Profiler;
+use chip8::rom::{self, Rom};
+use chip8::{rom_warning, Action, Bindings, CodeWrite, Config, Database, Filter, Instruction, Palette, Platform, Scheduler, Timing};
+use emulator::Emulator;
+use launcher::Launcher;
+use memory::MemoryView;
+use menu::CheatMenu;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_HZ: f64 = 500.0;
+const DEFAULT_TITLE: &str = "Chip8";
+
+// Settings from the command line and the configuration file, which apply to
+// whichever ROM is loaded.
+pub struct Settings {
+    pub config: Config,
+    pub database: Database,
+    pub platform: Option<Platform>,
+    pub quirks: Vec<String>,
+    pub hz: Option<f64>,
+    pub palette: Option<Palette>,
+    pub filter: Filter,
+    pub vip_timing: bool,
+    pub speed: f64,
+    pub fast_forward: f64,
+    pub profile: bool,
+    pub coverage: bool,
+}
+
+impl Settings {
+    // The palette from the command line, or else the ROM, or else the
+    // configuration file.
+    fn palette(&self, rom_palette: Option<&str>) -> Result<Palette, String> {
+        match self.palette {
+            Some(palette) => Ok(palette),
+            None => rom_palette.or_else(|| self.config.get("display", "palette")).map_or(Ok(Palette::default()), str::parse),
+        }
+    }
+
+    // Sets up an emulator for a ROM, combining these settings with the
+    // options the ROM came with and what the ROM database knows about it, in
+    // that order of priority. Returns it with the title to show.
+    fn load(&self, path: &Path, rom: Rom) -> Result<(Emulator, String), String> {
+        let info = self.database.lookup(&rom.data)?;
+
+        let name = path.file_stem().map_or("chip8".to_string(), |stem| stem.to_string_lossy().into_owned());
+        let bindings = Bindings::load(&self.config, Some(&name), &info.keys)?;
+
+        let mut quirks = self.platform.or(info.platform).unwrap_or_default().quirks();
+        for setting in info.quirks.iter().chain(&rom.quirks).chain(&self.quirks) {
+            quirks.apply(setting)?;
+        }
+
+        let hz = self.hz.or(rom.hz).or(info.hz).unwrap_or(DEFAULT_HZ);
+        let timing = if self.vip_timing { Timing::Vip } else { Timing::Instructions(hz) };
+        let mut scheduler = Scheduler::new(timing);
+        scheduler.set_speed(self.speed);
+
+        let palette = self.palette(rom.palette.as_deref().or(info.palette.as_deref()))?;
+        let mut emulator = Emulator::new(rom.data, name, quirks, scheduler, bindings, palette, self.filter)
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+        emulator.set_fast_forward(self.fast_forward);
+        emulator.load_cheats()?;
+        if self.profile {
+            emulator.profiler = Some(Profiler::new(self.vip_timing));
+            emulator.chip8.set_cycle_counting(true);
+        }
+        if self.coverage {
+            emulator.coverage = Some(Coverage::new(emulator.chip8.get_memory().len()));
+        }
+
+        Ok((emulator, info.name.unwrap_or_else(|| DEFAULT_TITLE.to_string())))
+    }
+}
+
+// What the frontends drive: the emulator for the current ROM, the launcher
+// to pick another one, the cheat menu and the memory view. Frontends only
+// translate their events into input names and call `update` with the
+// elapsed time.
+pub struct App {
+    pub emulator: Option<Emulator>,
+    pub launcher: Option<Launcher>,
+    // Shown over the emulator, which is paused meanwhile.
+    pub menu: Option<CheatMenu>,
+    pub memory: Option<MemoryView>,
+    // Toggled by the fullscreen action, for frontends that have a window.
+    pub fullscreen: bool,
+    settings: Settings,
+    // Used while no ROM is loaded.
+    bindings: Bindings,
+    palette: Palette,
+    games: PathBuf,
+    title: String,
+}
+
+impl App {
+    pub fn new(settings: Settings, games: PathBuf) -> Result<Self, String> {
+        Ok(App {
+            emulator: None,
+            launcher: None,
+            menu: None,
+            memory: None,
+            fullscreen: false,
+            bindings: Bindings::load(&settings.config, None, &[])?,
+            palette: settings.palette(None)?,
+            settings,
+            games,
+            title: DEFAULT_TITLE.to_string(),
+        })
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.emulator.as_ref().map_or(self.palette, |emulator| emulator.palette)
+    }
+
+    // Loads a ROM in place of the current one, returning a warning worth
+    // showing if there is one. Archives holding more than one ROM are shown
+    // in the launcher to pick from.
+    pub fn open(&mut self, path: &Path) -> Result<Option<String>, String> {
+        if rom::is_archive(path) && path.is_file() {
+            let entries = rom::archive_entries(path)?;
+            if entries.len() == 1 {
+                return self.open(&entries[0]);
+            }
+            self.launcher = Some(Launcher::new(path, &self.settings.database)?);
+            return Ok(None);
+        }
+
+        let rom = rom::read(path)?;
+        let warning = rom_warning(&rom.data).map(|warning| format!("warning: {}: {}", path.display(), warning));
+
+        let (emulator, title) = self.settings.load(path, rom)?;
+        // Only the profile of the last ROM is saved on exit.
+        let discarded = match self.emulator.replace(emulator) {
+            Some(ref previous) if previous.profiler.is_some() => Some(format!("warning: the profile of {} is discarded, only the last ROM's is saved", previous.name())),
+            _ => None,
+        };
+        self.launcher = None;
+        self.menu = None;
+        self.memory = None;
+        self.title = title;
+        Ok(warning.or(discarded))
+    }
+
+    pub fn show_launcher(&mut self) -> Result<(), String> {
+        self.launcher = Some(Launcher::new(&self.games, &self.settings.database)?);
+        Ok(())
+    }
+
+    // Returns a message for the frontend to show, if the input produced one.
+    pub fn input(&mut self, name: &str, pressed: bool) -> Option<String> {
+        let bindings = self.emulator.as_ref().map_or(&self.bindings, Emulator::bindings);
+        match (bindings.get(name), pressed) {
+            // Closing the launcher only makes sense with a ROM to go back to.
+            (Some(Action::Launcher), false) if self.launcher.is_some() => {
+                if self.emulator.is_some() {
+                    self.launcher = None;
+                }
+                return None;
+            }
+            (Some(Action::Launcher), false) => return self.show_launcher().err(),
+            (Some(Action::Cheats), false) if self.launcher.is_none() && self.emulator.is_some() => {
+                self.menu = if self.menu.is_some() { None } else { Some(CheatMenu::default()) };
+                return None;
+            }
+            (Some(Action::Memory), false) if self.launcher.is_none() && self.emulator.is_some() => {
+                self.memory = if self.memory.is_some() { None } else { Some(MemoryView::default()) };
+                return None;
+            }
+            (Some(Action::Fullscreen), false) => {
+                self.fullscreen = !self.fullscreen;
+                return None;
+            }
+            _ => (),
+        }
+
+        if let Some(ref mut launcher) = self.launcher {
+            let path = launcher.input(name, pressed)?;
+            return self.open(&path).unwrap_or_else(Some);
+        }
+
+        let emulator = self.emulator.as_mut()?;
+        if let Some(ref mut menu) = self.menu {
+            return menu.input(name, pressed, emulator);
+        }
+        // The memory view leaves the inputs it doesn't use to the emulator.
+        let used = self.memory.as_mut().is_some_and(|memory| memory.input(name, pressed, &mut emulator.chip8));
+        if used {
+            return None;
+        }
+        emulator.input(name, pressed)
+    }
+
+    // Runs the emulator unless the launcher or the cheat menu is shown, see
+    // `Emulator::update`.
+    pub fn update(&mut self, dt: f64) -> Option<Instruction> {
+        if self.launcher.is_some() || self.menu.is_some() {
+            return None;
+        }
+        self.emulator.as_mut()?.update(dt)
+    }
+
+    // See `Emulator::take_halt`.
+    pub fn take_halt(&mut self) -> Option<String> {
+        self.emulator.as_mut().and_then(Emulator::take_halt)
+    }
+
+    // See `Emulator::take_code_writes`.
+    pub fn take_code_writes(&mut self) -> Vec<CodeWrite> {
+        self.emulator.as_mut().map_or(Vec::new(), Emulator::take_code_writes)
+    }
+}
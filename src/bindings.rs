@@ -0,0 +1,166 @@
+use config::Config;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Keypad(u8),
+    Step,
+    Next,
+    Reset,
+    Palette,
+    Screenshot,
+    Fullscreen,
+    Pause,
+    FastForward,
+    Frame,
+    Slower,
+    Faster,
+    Launcher,
+    Cheats,
+    Memory,
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "step" => Ok(Action::Step),
+            "next" => Ok(Action::Next),
+            "reset" => Ok(Action::Reset),
+            "palette" => Ok(Action::Palette),
+            "screenshot" => Ok(Action::Screenshot),
+            "fullscreen" => Ok(Action::Fullscreen),
+            "pause" => Ok(Action::Pause),
+            "fast_forward" => Ok(Action::FastForward),
+            "frame" => Ok(Action::Frame),
+            "slower" => Ok(Action::Slower),
+            "faster" => Ok(Action::Faster),
+            "launcher" => Ok(Action::Launcher),
+            "cheats" => Ok(Action::Cheats),
+            "memory" => Ok(Action::Memory),
+            nibble if nibble.len() == 1 => u8::from_str_radix(nibble, 16)
+                .map(Action::Keypad)
+                .map_err(|_| format!("invalid keypad key '{}'", s)),
+            _ => Err(format!("unknown action '{}'", s)),
+        }
+    }
+}
+
+// Maps host input names to keypad keys and emulator actions. Names are in
+// lower case, like `q`, `1`, `space`, `lshift`, `numpad4` or `pad_button0`;
+// it's up to each frontend to name its keys.
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    actions: HashMap<String, Action>,
+}
+
+pub const DEFAULT_BINDINGS: [(&str, Action); 36] = [
+    ("1", Action::Keypad(0x1)),
+    ("2", Action::Keypad(0x2)),
+    ("3", Action::Keypad(0x3)),
+    ("4", Action::Keypad(0xC)),
+    ("q", Action::Keypad(0x4)),
+    ("w", Action::Keypad(0x5)),
+    ("e", Action::Keypad(0x6)),
+    ("r", Action::Keypad(0xD)),
+    ("a", Action::Keypad(0x7)),
+    ("s", Action::Keypad(0x8)),
+    ("d", Action::Keypad(0x9)),
+    ("f", Action::Keypad(0xE)),
+    ("z", Action::Keypad(0xA)),
+    ("x", Action::Keypad(0x0)),
+    ("c", Action::Keypad(0xB)),
+    ("v", Action::Keypad(0xF)),
+    ("space", Action::Next),
+    ("tab", Action::Reset),
+    ("p", Action::Palette),
+    ("f1", Action::Launcher),
+    ("f3", Action::Cheats),
+    ("f4", Action::Memory),
+    ("f5", Action::Pause),
+    ("f6", Action::Frame),
+    ("f7", Action::Step),
+    ("minus", Action::Slower),
+    ("equals", Action::Faster),
+    ("backquote", Action::FastForward),
+    ("f11", Action::Fullscreen),
+    ("f12", Action::Screenshot),
+    ("pad_up", Action::Keypad(0x2)),
+    ("pad_down", Action::Keypad(0x8)),
+    ("pad_left", Action::Keypad(0x4)),
+    ("pad_right", Action::Keypad(0x6)),
+    ("pad_button0", Action::Keypad(0x5)),
+    ("pad_button1", Action::Keypad(0x0)),
+];
+
+impl Bindings {
+    // Applies the `[keys]` section of the configuration, then the bindings
+    // from the ROM database and finally the `[keys <rom>]` section for the
+    // given ROM name, if any. A value of `none` removes a binding.
+    pub fn load(config: &Config, rom: Option<&str>, database: &[(String, String)]) -> Result<Self, String> {
+        let mut bindings = Self::default();
+        bindings.apply(config.entries("keys"))?;
+        bindings.apply(database.iter().map(|(name, value)| (name.as_str(), value.as_str())))?;
+        if let Some(rom) = rom {
+            bindings.apply(config.entries(&format!("keys {}", rom)))?;
+        }
+        Ok(bindings)
+    }
+
+    pub fn apply<'a, I>(&mut self, entries: I) -> Result<(), String>
+    where
+        I: IntoIterator<Item = (&'a str, &'a str)>,
+    {
+        for (name, value) in entries {
+            if value.eq_ignore_ascii_case("none") {
+                self.actions.remove(&name.to_lowercase());
+            } else {
+                let action = value.parse().map_err(|error| format!("binding for '{}': {}", name, error))?;
+                self.actions.insert(name.to_lowercase(), action);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Action> {
+        self.actions.get(name).cloned()
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            actions: DEFAULT_BINDINGS.iter().map(|&(name, action)| (name.to_string(), action)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn load() {
+        let config = Config::parse("[keys]\nleft = 4\nq = none\n[keys TETRIS]\nleft = 5\nspace = reset\n").unwrap();
+
+        let bindings = Bindings::load(&config, None, &[]).unwrap();
+        assert_eq!(bindings.get("left"), Some(Action::Keypad(0x4)));
+        assert_eq!(bindings.get("q"), None);
+        assert_eq!(bindings.get("w"), Some(Action::Keypad(0x5)));
+
+        let database = [("left".to_string(), "6".to_string()), ("right".to_string(), "7".to_string()), ("W".to_string(), "none".to_string())];
+        let bindings = Bindings::load(&config, Some("TETRIS"), &database).unwrap();
+        assert_eq!(bindings.get("w"), None);
+        assert_eq!(bindings.get("left"), Some(Action::Keypad(0x5)));
+        assert_eq!(bindings.get("right"), Some(Action::Keypad(0x7)));
+        assert_eq!(bindings.get("space"), Some(Action::Reset));
+    }
+
+    #[test]
+    fn load_invalid() {
+        let config = Config::parse("[keys]\nleft = 10\n").unwrap();
+        assert!(Bindings::load(&config, None, &[]).is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use gif;
+use octo;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// An Octo cartridge: a GIF image with a JSON payload hidden in the two lowest
+// bits of every pixel's colour index, four pixels to a byte. The payload
+// starts with its length as four big endian bytes and holds the program
+// together with the options it was shared with:
+//
+//     {"program": ": main ...", "options": {"tickrate": 20, "fillColor": "#FFCC00", ...}}
+//
+// The program is Octo source code, which is assembled on loading.
+pub struct Cartridge {
+    pub program: Vec<u8>,
+    pub hz: Option<f64>,
+    pub palette: Option<String>,
+    pub quirks: Vec<String>,
+}
+
+// Octo ROMs are loaded at 0x200, so anything larger than the 3584 bytes up
+// to 4 KiB needs XO-CHIP memory.
+const SMALL_MEMORY_ROM_SIZE: f64 = 3584.0;
+
+// Octo's quirk options and the quirks they set.
+const QUIRK_FLAGS: [(&str, &str); 5] = [
+    ("clipQuirks", "clip_sprites"),
+    ("shiftQuirks", "shift_vx"),
+    ("loadStoreQuirks", "load_store_keeps_i"),
+    ("jumpQuirks", "jump_vx"),
+    ("logicQuirks", "logic_resets_vf"),
+];
+
+impl Cartridge {
+    pub fn decode(image: &[u8]) -> Result<Self, String> {
+        let payload = payload(image)?;
+        let json = parse_json(&payload).ok_or_else(|| "invalid cartridge payload".to_string())?;
+
+        let program = match json.get("program") {
+            Some(Json::String(source)) => octo::assemble(source).map_err(|error| format!("the cartridge's program doesn't assemble: {}", error))?,
+            _ => return Err("the cartridge has no program".to_string()),
+        };
+        let options = json.get("options");
+        let option = |name| options.and_then(|options| options.get(name));
+        let number = |name| match option(name) {
+            Some(&Json::Number(number)) => Some(number),
+            _ => None,
+        };
+        let colour = |name| match option(name) {
+            Some(Json::String(colour)) => Some(colour.trim_start_matches('#').to_string()),
+            _ => None,
+        };
+
+        // Octo's vBlankQuirks, drawing only once per frame, isn't emulated.
+        let mut quirks = Vec::new();
+        for &(flag, quirk) in &QUIRK_FLAGS {
+            if let Some(&Json::Bool(value)) = option(flag) {
+                quirks.push(format!("{}={}", quirk, value));
+            }
+        }
+        if let Some(size) = number("maxSize") {
+            quirks.push(format!("large_memory={}", size > SMALL_MEMORY_ROM_SIZE));
+        }
+
+        let colours: Option<Vec<String>> = ["backgroundColor", "fillColor", "fillColor2", "blendColor"].iter().map(|name| colour(name)).collect();
+        Ok(Cartridge {
+            program,
+            // The tick rate is the number of instructions per 60 Hz frame.
+            hz: number("tickrate").map(|tickrate| tickrate * 60.0),
+            palette: colours.map(|colours| colours.join(" ")),
+            quirks,
+        })
+    }
+}
+
+fn payload(image: &[u8]) -> Result<String, String> {
+    let mut reader = gif::Decoder::new(image).read_info().map_err(|error| format!("invalid GIF: {}", error))?;
+
+    let mut bytes = Vec::new();
+    let mut byte = 0;
+    let mut pixels = 0;
+    while let Some(frame) = reader.read_next_frame().map_err(|error| format!("invalid GIF: {}", error))? {
+        for index in frame.buffer.iter() {
+            byte = byte << 2 | (index & 0x03);
+            pixels += 1;
+            if pixels & 0x03 == 0 {
+                bytes.push(byte);
+                byte = 0;
+            }
+        }
+    }
+
+    let length = bytes.iter().take(4).fold(0, |length, &byte| length << 8 | byte as usize);
+    match bytes.get(4..4 + length) {
+        Some(payload) if bytes.len() >= 4 => Ok(payload.iter().map(|&byte| byte as char).collect()),
+        _ => Err("not an Octo cartridge".to_string()),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref members) => members.iter().find(|member| member.0 == key).map(|member| &member.1),
+            _ => None,
+        }
+    }
+}
+
+fn parse_json(text: &str) -> Option<Json> {
+    let mut chars = text.chars().peekable();
+    let value = parse_value(&mut chars)?;
+    skip_whitespace(&mut chars);
+    match chars.next() {
+        None => Some(value),
+        Some(_) => None,
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Option<Json> {
+    skip_whitespace(chars);
+    match *chars.peek()? {
+        '{' => {
+            chars.next();
+            let mut members = Vec::new();
+            loop {
+                skip_whitespace(chars);
+                match chars.next()? {
+                    '}' if members.is_empty() => return Some(Json::Object(members)),
+                    '"' => (),
+                    _ => return None,
+                }
+                let key = parse_string(chars)?;
+                skip_whitespace(chars);
+                if chars.next()? != ':' {
+                    return None;
+                }
+                members.push((key, parse_value(chars)?));
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => (),
+                    '}' => return Some(Json::Object(members)),
+                    _ => return None,
+                }
+            }
+        }
+        '[' => {
+            chars.next();
+            let mut values = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Some(Json::Array(values));
+            }
+            loop {
+                values.push(parse_value(chars)?);
+                skip_whitespace(chars);
+                match chars.next()? {
+                    ',' => (),
+                    ']' => return Some(Json::Array(values)),
+                    _ => return None,
+                }
+            }
+        }
+        '"' => {
+            chars.next();
+            parse_string(chars).map(Json::String)
+        }
+        _ => {
+            let mut word = String::new();
+            while chars.peek().is_some_and(|&c| c.is_alphanumeric() || "+-.".contains(c)) {
+                word.push(chars.next()?);
+            }
+            match word.as_str() {
+                "null" => Some(Json::Null),
+                "true" => Some(Json::Bool(true)),
+                "false" => Some(Json::Bool(false)),
+                _ => word.parse().ok().map(Json::Number),
+            }
+        }
+    }
+}
+
+// Parses the rest of a string after the opening quote.
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => string.push(match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    ::std::char::from_u32(u32::from_str_radix(&hex, 16).ok()?).unwrap_or('\u{fffd}')
+                }
+                c => c,
+            }),
+            c => string.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Hides a payload in a GIF the way Octo does, a frame of 64x32 pixels at
+    // a time.
+    fn cartridge(payload: &str) -> Vec<u8> {
+        let mut bytes = vec![0, 0, (payload.len() >> 8) as u8, payload.len() as u8];
+        bytes.extend(payload.bytes());
+
+        let mut pixels: Vec<u8> = bytes.iter().flat_map(|&byte| vec![byte >> 6, byte >> 4 & 3, byte >> 2 & 3, byte & 3]).collect();
+        let frame_size = 64 * 32;
+        let frames = pixels.len().div_ceil(frame_size);
+        pixels.resize(frames * frame_size, 0);
+
+        let mut image = Vec::new();
+        {
+            let palette = [0, 0, 0, 85, 85, 85, 170, 170, 170, 255, 255, 255];
+            let mut encoder = gif::Encoder::new(&mut image, 64, 32, &palette).unwrap();
+            for frame in pixels.chunks(frame_size) {
+                encoder.write_frame(&gif::Frame::from_indexed_pixels(64, 32, frame, None)).unwrap();
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn decode() {
+        let payload = r##"{"program": ": main\n  loop again", "options": {"tickrate": 20, "clipQuirks": true, "shiftQuirks": false, "maxSize": 65024,
+            "backgroundColor": "#996600", "fillColor": "#FFCC00", "fillColor2": "#FF6600", "blendColor": "#662200", "touchInputMode": "none"}}"##;
+        let cartridge = Cartridge::decode(&cartridge(payload)).unwrap();
+
+        assert_eq!(cartridge.program, [0x12, 0x02, 0x12, 0x02]);
+        assert_eq!(cartridge.hz, Some(1200.0));
+        assert_eq!(cartridge.palette.as_deref(), Some("996600 FFCC00 FF6600 662200"));
+        assert_eq!(cartridge.quirks, vec!["clip_sprites=true", "shift_vx=false", "large_memory=true"]);
+    }
+
+    #[test]
+    fn decode_invalid() {
+        assert!(Cartridge::decode(b"GIF89a").is_err());
+        assert!(Cartridge::decode(&cartridge("{}")).is_err());
+        assert!(Cartridge::decode(&cartridge(r#"{"program": ": start"}"#)).is_err());
+    }
+
+    #[test]
+    fn json() {
+        let json = parse_json(r#" {"a": [1, -2.5e1, "\"A"], "b": {}, "c": null, "d": false} "#).unwrap();
+        assert_eq!(
+            json.get("a"),
+            Some(&Json::Array(vec![Json::Number(1.0), Json::Number(-25.0), Json::String("\"A".to_string())]))
+        );
+        assert_eq!(json.get("b"), Some(&Json::Object(Vec::new())));
+        assert_eq!(json.get("c"), Some(&Json::Null));
+        assert_eq!(json.get("d"), Some(&Json::Bool(false)));
+        assert_eq!(parse_json("[1,]"), None);
+        assert_eq!(parse_json("{} {}"), None);
+    }
+}
@@ -0,0 +1,202 @@
+use chip8::Chip8;
+use config::{self, Config};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+    Unchanged,
+    Changed,
+    Increased,
+    Decreased,
+    Equal(u8),
+}
+
+impl Condition {
+    fn matches(self, before: u8, after: u8) -> bool {
+        match self {
+            Condition::Unchanged => after == before,
+            Condition::Changed => after != before,
+            Condition::Increased => after > before,
+            Condition::Decreased => after < before,
+            Condition::Equal(value) => after == value,
+        }
+    }
+}
+
+// Narrows down the addresses that could hold a value such as the number of
+// lives, by comparing memory with a snapshot taken at the previous step.
+pub struct Search {
+    snapshot: Vec<u8>,
+    candidates: Vec<usize>,
+}
+
+impl Search {
+    pub fn new(memory: &[u8]) -> Self {
+        Search {
+            snapshot: memory.to_vec(),
+            candidates: (0..memory.len()).collect(),
+        }
+    }
+
+    pub fn filter(&mut self, memory: &[u8], condition: Condition) {
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&address| condition.matches(snapshot[address], memory[address]));
+        self.snapshot = memory.to_vec();
+    }
+
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+
+    // The value at an address when the last snapshot was taken.
+    pub fn value(&self, address: usize) -> u8 {
+        self.snapshot[address]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cheat {
+    pub address: usize,
+    pub value: u8,
+    pub enabled: bool,
+}
+
+// Memory locations pinned to a value, saved per ROM as a file named after its
+// SHA-1 in the data directory:
+//
+//     [cheats]
+//     # address = value, in hex, and `off` when disabled
+//     2a5 = 03
+//     3f0 = 99 off
+#[derive(Debug, Default, PartialEq)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+// `cheats` in the data directory.
+pub fn directory() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("cheats"))
+}
+
+impl Cheats {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let config = Config::parse(text)?;
+        let mut cheats = Cheats::default();
+        for (address, setting) in config.entries("cheats") {
+            let invalid = || format!("invalid cheat '{} = {}'", address, setting);
+            let mut parts = setting.split_whitespace();
+            let address = usize::from_str_radix(address, 16).map_err(|_| invalid())?;
+            let value = parts.next().and_then(|value| u8::from_str_radix(value, 16).ok()).ok_or_else(invalid)?;
+            let enabled = match parts.next() {
+                None => true,
+                Some("off") => false,
+                Some(_) => return Err(invalid()),
+            };
+            cheats.add(address, value);
+            cheats.cheats.last_mut().unwrap().enabled = enabled;
+        }
+        Ok(cheats)
+    }
+
+    fn path(dir: &Path, hash: &str) -> PathBuf {
+        dir.join(format!("{}.cfg", hash))
+    }
+
+    // Loads the cheats for the ROM with the given hash, there are none if the
+    // file doesn't exist.
+    pub fn load(dir: &Path, hash: &str) -> Result<Self, String> {
+        let path = Self::path(dir, hash);
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).map_err(|error| format!("{}: {}", path.display(), error)),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(Cheats::default()),
+            Err(error) => Err(format!("{}: {}", path.display(), error)),
+        }
+    }
+
+    pub fn save(&self, dir: &Path, hash: &str) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let path = Self::path(dir, hash);
+        fs::write(&path, self.to_text())?;
+        Ok(path)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = "[cheats]\n".to_string();
+        for cheat in &self.cheats {
+            let state = if cheat.enabled { "" } else { " off" };
+            text += &format!("{:03x} = {:02x}{}\n", cheat.address, cheat.value, state);
+        }
+        text
+    }
+
+    pub fn get(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    // Pins an address to a value, replacing any cheat for that address.
+    pub fn add(&mut self, address: usize, value: u8) {
+        self.cheats.retain(|cheat| cheat.address != address);
+        self.cheats.push(Cheat { address, value, enabled: true });
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.cheats.remove(index);
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        self.cheats[index].enabled ^= true;
+    }
+
+    // Writes the enabled cheats to memory, once every frame.
+    pub fn apply(&self, chip8: &mut Chip8) {
+        for cheat in self.cheats.iter().filter(|cheat| cheat.enabled) {
+            chip8.set_memory(cheat.address, cheat.value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn search() {
+        let mut memory = vec![3, 3, 5, 0];
+        let mut search = Search::new(&memory);
+        memory[0] = 2;
+        memory[2] = 6;
+        search.filter(&memory, Condition::Changed);
+        assert_eq!(search.candidates(), [0, 2]);
+        memory[0] = 1;
+        search.filter(&memory, Condition::Decreased);
+        assert_eq!(search.candidates(), [0]);
+        search.filter(&memory, Condition::Equal(2));
+        assert!(search.candidates().is_empty());
+    }
+
+    #[test]
+    fn parse() {
+        let mut cheats = Cheats::parse("[cheats]\n2a5 = 03\n3F0 = 99 off\n").unwrap();
+        assert_eq!(
+            cheats.get(),
+            [Cheat { address: 0x2A5, value: 3, enabled: true }, Cheat { address: 0x3F0, value: 0x99, enabled: false }]
+        );
+        cheats.add(0x2A5, 4);
+        assert_eq!(cheats.to_text(), "[cheats]\n3f0 = 99 off\n2a5 = 04\n");
+        assert!(Cheats::parse("[cheats]\n2a5 = 300\n").is_err());
+        assert!(Cheats::parse("[cheats]\nlives = 3\n").is_err());
+    }
+
+    #[test]
+    fn apply() {
+        let mut chip8 = Chip8::new(&[0x00, 0xE0]).unwrap();
+        let mut cheats = Cheats::default();
+        cheats.add(0x300, 7);
+        cheats.add(0x301, 8);
+        cheats.toggle(1);
+        cheats.apply(&mut chip8);
+        assert_eq!(chip8.get_memory()[0x300..0x302], [7, 0]);
+    }
+}
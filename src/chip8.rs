@@ -1,13 +1,22 @@
-use super::instruction::Instruction;
+use super::display::{Display, Resolution, PLANES};
+use super::instruction::{Instruction, LONG_OPCODE};
+use super::platform::{Platform, Quirks};
 use rand;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 
-pub const WIDTH: usize = 128;
-pub const HEIGHT: usize = 64;
 const REGISTER_COUNT: usize = 16;
 const STACK_SIZE: usize = 16;
-const MEMORY_SIZE: usize = 4096;
-const ROM_OFFSET: usize = 512;
+pub const ROM_OFFSET: usize = 512;
 const DEBUG_MEMORY_ROW_SIZE: usize = 64;
+// How many of the addresses last written by the program are remembered.
+const RECENT_WRITES: usize = 32;
+// How many writes over code are kept until taken.
+const MAX_CODE_WRITES: usize = 64;
+
+// The COSMAC VIP runs at 1.76 MHz with 8 clocks per machine cycle, which
+// leaves roughly 3668 machine cycles between two 60 Hz display interrupts.
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
 
 const FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -28,45 +37,235 @@ const FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+#[derive(Debug, PartialEq)]
+pub enum RomError {
+    Empty,
+    // The ROM doesn't fit between 0x200 and the end of memory.
+    TooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RomError::Empty => write!(f, "the ROM is empty"),
+            RomError::TooLarge { size, max } => write!(
+                f,
+                "the ROM is {} bytes but at most {} fit in memory, try a platform with more memory such as xochip",
+                size, max
+            ),
+        }
+    }
+}
+
+// Instructions are two bytes, so an odd length hints at a truncated file or
+// something that isn't a ROM at all. Plenty of ROMs end in an odd number of
+// bytes of data though, so this is only worth a warning.
+pub fn rom_warning(data: &[u8]) -> Option<String> {
+    if data.len() % 2 == 1 {
+        Some(format!("the ROM has an odd length of {} bytes, it may be truncated", data.len()))
+    } else {
+        None
+    }
+}
+
+// A write by the program over its own code, either to a byte of an
+// instruction that already ran or to one that runs later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CodeWrite {
+    // The instruction that did the write.
+    pub pc: u16,
+    pub address: usize,
+    pub ran_before: bool,
+}
+
+impl fmt::Display for CodeWrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let when = if self.ran_before { "already ran" } else { "ran later" };
+        write!(f, "{:03X} wrote over code at {:03X}, which {}", self.pc, self.address, when)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Running,
+    // Executing FX0A: the key is captured when pressed and the instruction
+    // completes once it is released again, like on the COSMAC VIP.
+    WaitingForKey { x: usize, key: Option<u8> },
+    // Stopped at the instruction at `pc`, which couldn't run, until reset.
+    Halted { pc: u16, halt: Halt },
+}
+
+// Why the program stopped running.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Halt {
+    // `EXIT`, which SCHIP programs end with.
+    Exit,
+    // A `CALL` with all 16 levels of the stack in use.
+    StackOverflow,
+    // A `RET` without a `CALL` to return from.
+    StackUnderflow,
+    Unsupported,
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match *self {
+            Halt::Exit => "the program exited",
+            Halt::StackOverflow => "stack overflow",
+            Halt::StackUnderflow => "return with an empty stack",
+            Halt::Unsupported => "unsupported instruction",
+        };
+        f.write_str(text)
+    }
+}
+
 pub struct Chip8 {
-    super_mode: bool,
+    quirks: Quirks,
+    state: State,
     i: u16,
     pc: u16,
-    mem: [u8; MEMORY_SIZE],
+    mem: Vec<u8>,
     regs: [u8; REGISTER_COUNT],
     keypad: u16,
-    display: [bool; WIDTH * HEIGHT],
+    key_events: VecDeque<(u8, bool)>,
+    // Oldest first, each address only once.
+    recent_writes: VecDeque<usize>,
+    // Per byte of memory, whether it was fetched as part of an instruction,
+    // and for the few written since they last ran, the instruction that
+    // wrote them.
+    executed: Vec<bool>,
+    written_by: HashMap<usize, u16>,
+    code_writes: Vec<CodeWrite>,
+    // The instruction decoded at each address of the loaded program, cleared
+    // when any of its bytes is written. Code run from elsewhere, which few
+    // programs do, is decoded every time.
+    decoded: Vec<Option<Instruction>>,
+    decode_cache: bool,
+    // Whether `tick` works out the VIP cycles, only needed for VIP timing
+    // and profiling.
+    count_cycles: bool,
+    display: Display,
     stack: [u16; STACK_SIZE],
     sp: u8,
     dt: u8,
-}
-
-fn offset(x: usize, y: usize) -> usize {
-    (x % WIDTH) + ((y % HEIGHT) * WIDTH)
+    cycles: u32,
 }
 
 impl Chip8 {
-    pub fn new(data: &[u8]) -> Self {
-        let mut mem = [0; MEMORY_SIZE];
+    pub fn new(data: &[u8]) -> Result<Self, RomError> {
+        Self::with_quirks(data, Platform::default().quirks())
+    }
+
+    pub fn with_quirks(data: &[u8], quirks: Quirks) -> Result<Self, RomError> {
+        let mut mem = vec![0; quirks.memory_size()];
+        let max = mem.len() - ROM_OFFSET;
+        if data.is_empty() {
+            return Err(RomError::Empty);
+        } else if data.len() > max {
+            return Err(RomError::TooLarge { size: data.len(), max });
+        }
+
         mem[0..FONT_SET.len()].copy_from_slice(&FONT_SET);
         mem[ROM_OFFSET..(ROM_OFFSET + data.len())].copy_from_slice(data);
 
-        Chip8 {
-            super_mode: false,
+        Ok(Chip8 {
+            quirks,
+            state: State::Running,
             i: 0,
             pc: 0x0200,
-            mem: mem,
+            mem,
             regs: [0; REGISTER_COUNT],
             keypad: 0,
-            display: [false; WIDTH * HEIGHT],
+            key_events: VecDeque::new(),
+            recent_writes: VecDeque::new(),
+            executed: vec![false; quirks.memory_size()],
+            written_by: HashMap::new(),
+            code_writes: Vec::new(),
+            decoded: vec![None; ROM_OFFSET + data.len()],
+            decode_cache: true,
+            count_cycles: false,
+            display: Display::new(),
             stack: [0; 16],
             sp: 0,
             dt: 0,
+            cycles: 0,
+        })
+    }
+
+    pub fn get_display(&self) -> &Display {
+        &self.display
+    }
+
+    pub fn get_memory(&self) -> &[u8] {
+        &self.mem
+    }
+
+    // Writes to memory from outside the program, e.g. for cheats. Addresses
+    // beyond the end of memory are ignored.
+    pub fn set_memory(&mut self, address: usize, value: u8) {
+        if let Some(byte) = self.mem.get_mut(address) {
+            *byte = value;
+            self.invalidate(address);
+        }
+    }
+
+    // Decoding every instruction again as it runs is only useful to compare
+    // against the cache, as the benchmark does. Without the cache, code
+    // writes aren't tracked either, so that's a plain fetch and decode.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = enabled;
+        self.decoded.iter_mut().for_each(|decoded| *decoded = None);
+    }
+
+    // See `get_cycles`.
+    pub fn set_cycle_counting(&mut self, enabled: bool) {
+        self.count_cycles = enabled;
+        if !enabled {
+            self.cycles = 0;
         }
     }
 
-    pub fn get_display(&self) -> &[bool] {
-        &self.display[..]
+    // Whether the display changed since the last call.
+    pub fn take_display_dirty(&mut self) -> bool {
+        self.display.take_dirty()
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn get_i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    // The width and height of the sprites `DRW Vx, Vy, 0` draws.
+    pub fn large_sprite_size(&self) -> (usize, usize) {
+        let low = self.display.get_resolution() == Resolution::Low;
+        if low && self.quirks.lores_empty_sprites {
+            (8, 0)
+        } else if low && self.quirks.lores_tall_sprites {
+            (8, 16)
+        } else {
+            (16, 16)
+        }
+    }
+
+    // The addresses the program wrote to last, oldest first.
+    pub fn get_recent_writes(&self) -> &VecDeque<usize> {
+        &self.recent_writes
+    }
+
+    // The writes over code since the last call, up to a limit.
+    pub fn take_code_writes(&mut self) -> Vec<CodeWrite> {
+        std::mem::take(&mut self.code_writes)
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.dt
     }
 
     pub fn apply_keypad_value(&mut self, index: u8, pressed: bool) {
@@ -77,126 +276,251 @@ impl Chip8 {
         } else {
             self.keypad &= !(1 << index);
         }
+
+        match self.state {
+            State::WaitingForKey { x, key: None } if pressed => {
+                self.state = State::WaitingForKey { x, key: Some(index) }
+            }
+            State::WaitingForKey { x, key: Some(key) } if !pressed && key == index => {
+                self.regs[x] = key;
+                self.state = State::Running
+            }
+            _ => (),
+        }
+    }
+
+    // Queues a key change to be applied by `process_key_events`, so presses
+    // shorter than a frame still get seen by the program.
+    pub fn push_key_event(&mut self, index: u8, pressed: bool) {
+        assert!(index < 0x10, "index should be a nibble");
+        self.key_events.push_back((index, pressed));
+    }
+
+    // Applies queued key events in order, stopping before the release of a
+    // key that was pressed in this same batch. Called once per frame.
+    pub fn process_key_events(&mut self) {
+        let mut pressed_now = 0u16;
+
+        while let Some(&(index, pressed)) = self.key_events.front() {
+            if !pressed && pressed_now & (1 << index) != 0 {
+                break;
+            }
+
+            self.key_events.pop_front();
+            if pressed {
+                pressed_now |= 1 << index;
+            }
+            self.apply_keypad_value(index, pressed);
+        }
+    }
+
+    pub fn get_state(&self) -> State {
+        self.state
     }
 
     pub fn tick(&mut self) -> Instruction {
-        let opcode = self.fetch();
-        self.pc += 2;
-        let instruction = Instruction::from_opcode(opcode);
+        match self.state {
+            State::WaitingForKey { x, .. } => {
+                self.cycles = 0;
+                return Instruction::LDx { x };
+            }
+            State::Halted { pc, .. } => {
+                self.cycles = 0;
+                return Instruction::from_opcode(self.fetch(pc as usize));
+            }
+            State::Running => (),
+        }
+
+        // The PC wraps around at the end of memory.
+        self.pc &= (self.mem.len() - 1) as u16;
+        let pc = self.pc as usize;
+        let instruction = match self.decoded.get(pc) {
+            Some(&Some(instruction)) => instruction,
+            _ => self.decode(pc),
+        };
+        self.pc = self.pc.wrapping_add(instruction.size());
+        if self.count_cycles {
+            self.cycles = self.vip_cycles(&instruction);
+        }
         self.execute(&instruction);
         instruction
     }
 
+    // Machine cycles the last executed instruction would have taken on the
+    // COSMAC VIP interpreter, not counting any wait for the display interrupt.
+    // Always 0 unless enabled with `set_cycle_counting`.
+    pub fn get_cycles(&self) -> u32 {
+        self.cycles
+    }
+
     pub fn decrement_counter(&mut self) {
         if self.dt > 0 {
             self.dt -= 1
         };
     }
 
-    fn fetch(&self) -> u16 {
-        (self.mem[self.pc as usize] as u16) << 8 | (self.mem[(self.pc + 1) as usize] as u16)
+    // Decodes the instruction at `pc` for the first time since its bytes were
+    // last written, which is also when running them is worth checking for.
+    fn decode(&mut self, pc: usize) -> Instruction {
+        let instruction = match self.fetch(pc) {
+            LONG_OPCODE => Instruction::LDnnnn { nnnn: self.fetch(pc + 2) },
+            opcode => Instruction::from_opcode(opcode),
+        };
+        if !self.decode_cache {
+            return instruction;
+        }
+        for address in pc..pc + instruction.size() as usize {
+            let address = address & (self.mem.len() - 1);
+            self.executed[address] = true;
+            if let Some(writer) = self.written_by.remove(&address) {
+                self.push_code_write(CodeWrite { pc: writer, address, ran_before: false });
+            }
+        }
+        if let Some(decoded) = self.decoded.get_mut(pc) {
+            *decoded = Some(instruction);
+        }
+        instruction
+    }
+
+    // The word at `address`, wrapping around at the end of memory.
+    fn fetch(&self, address: usize) -> u16 {
+        let mask = self.mem.len() - 1;
+        (self.mem[address & mask] as u16) << 8 | (self.mem[(address + 1) & mask] as u16)
+    }
+
+    // Skips over the next instruction, which `LD I, long` takes four bytes
+    // for.
+    fn skip(&mut self) {
+        let size = if self.fetch(self.pc as usize) == LONG_OPCODE { 4 } else { 2 };
+        self.pc = self.pc.wrapping_add(size)
     }
 
     fn execute(&mut self, instruction: &Instruction) {
         use self::Instruction::*;
 
         match *instruction {
-            ADDix { x } => self.i += self.regs[x] as u16,
+            ADDix { x } => self.i = self.i.wrapping_add(self.regs[x] as u16),
             ADDxkk { x, kk } => self.regs[x] = self.regs[x].wrapping_add(kk),
             ADDxy { x, y } => {
                 let (new, carry) = self.regs[x].overflowing_add(self.regs[y]);
                 self.regs[x] = new;
                 self.regs[0xF] = carry as u8
             }
-            AND { x, y } => self.regs[x] &= self.regs[y],
+            AND { x, y } => {
+                self.regs[x] &= self.regs[y];
+                self.reset_vf()
+            }
             CALL { nnn } => {
+                if self.sp as usize == STACK_SIZE {
+                    return self.halt(Halt::StackOverflow);
+                }
                 self.stack[self.sp as usize] = self.pc;
                 self.sp += 1;
                 self.pc = nnn
             }
-            CLS => self.display.copy_from_slice(&[false; WIDTH * HEIGHT]),
-            DRW { x, y, n } => self.regs[0xF] = self.draw(x, y, n) as u8,
-            DRWH { x, y } => self.regs[0xF] = self.draw_16(x, y) as u8,
-            EXIT => panic!("EXIT not supported yet"), // ???
-            INVALID { opcode } => panic!("opcode {:#X} not supported", opcode),
-            HIGH => self.super_mode = true,
+            CLS => self.display.clear(),
+            DRW { x, y, n } => self.regs[0xF] = self.draw(x, y, 8, n as usize),
+            DRWH { x, y } => {
+                let (width, height) = self.large_sprite_size();
+                self.regs[0xF] = self.draw(x, y, width, height)
+            }
+            EXIT => self.halt(Halt::Exit),
+            INVALID { .. } => self.halt(Halt::Unsupported),
+            HIGH => self.display.set_resolution(Resolution::High),
+            PLANE { n } => self.display.set_planes(n),
             JPnnn { nnn } => self.pc = nnn,
-            JPnnnv { nnn } => self.pc = nnn + self.regs[0] as u16,
-            LDbx { x } => self.mem[self.i as usize..=self.i as usize + 2]
-                .copy_from_slice(&Self::get_bcd(self.regs[x])),
+            JPnnnv { nnn } => {
+                let x = if self.quirks.jump_vx { (nnn >> 8) as usize } else { 0 };
+                self.pc = nnn + self.regs[x] as u16
+            }
+            LDbx { x } => {
+                for (offset, &digit) in Self::get_bcd(self.regs[x]).iter().enumerate() {
+                    self.write_mem(self.i as usize + offset, digit)
+                }
+            }
             LDfx { x } => self.i = self.regs[x] as u16 * 5,
             LDhfx { x } => self.i = self.regs[x] as u16 * 10,
             LDix { x } => {
                 for i in 0..=x {
-                    self.mem[self.i as usize + i] = self.regs[i]
+                    self.write_mem(self.i as usize + i, self.regs[i])
                 }
+                self.advance_i(x)
             }
             LDnnn { nnn } => self.i = nnn,
-            LDrx { .. } => panic!("LDrx not supported yet"), // ???
-            LDsx { .. } => (),                               // Set sound timer register
+            LDnnnn { nnnn } => self.i = nnnn,
+            LDrx { .. } => self.halt(Halt::Unsupported),
+            LDsx { .. } => (), // Set sound timer register
             LDtx { x } => self.dt = self.regs[x],
             LDx { x } => {
-                if let Some(i) = self.check_keypad() {
-                    self.regs[x] = i
-                } else {
-                    self.pc -= 2
+                self.state = State::WaitingForKey {
+                    x,
+                    key: self.check_keypad(),
                 }
             }
             LDxi { x } => {
                 for i in 0..=x {
-                    self.regs[i] = self.mem[self.i as usize + i]
+                    self.regs[i] = self.read_mem(self.i as usize + i)
                 }
+                self.advance_i(x)
             }
             LDxkk { x, kk } => self.regs[x] = kk,
-            LDxr { .. } => panic!("LDxr not supported yet"), // ???
+            LDxr { .. } => self.halt(Halt::Unsupported),
             LDxt { x } => self.regs[x] = self.dt,
             LDxy { x, y } => self.regs[x] = self.regs[y],
-            LOW => self.super_mode = false,
-            OR { x, y } => self.regs[x] |= self.regs[y],
+            LOW => self.display.set_resolution(Resolution::Low),
+            OR { x, y } => {
+                self.regs[x] |= self.regs[y];
+                self.reset_vf()
+            }
             RET => {
+                if self.sp == 0 {
+                    return self.halt(Halt::StackUnderflow);
+                }
                 self.sp -= 1;
                 self.pc = self.stack[self.sp as usize]
             }
             RND { x, kk } => self.regs[x] = rand::random::<u8>() & kk,
-            SCDn { n } => self.scroll_down(n),
-            SCL => self.scroll_left(),
-            SCR => self.scroll_right(),
+            SCDn { n } => self.display.scroll_down(n as usize * self.scroll_scale()),
+            SCL => self.display.scroll_left(4 * self.scroll_scale()),
+            SCR => self.display.scroll_right(4 * self.scroll_scale()),
             SExkk { x, kk } => {
                 if self.regs[x] == kk {
-                    self.pc += 2
+                    self.skip()
                 }
             }
             SExy { x, y } => {
                 if self.regs[x] == self.regs[y] {
-                    self.pc += 2
+                    self.skip()
                 }
             }
-            SHL { x, .. } => {
-                self.regs[0xF] = self.regs[x] >> 7;
-                self.regs[x] <<= 1
+            SHL { x, y } => {
+                let value = self.regs[if self.quirks.shift_vx { x } else { y }];
+                self.regs[x] = value << 1;
+                self.regs[0xF] = value >> 7
             }
-            SHR { x, .. } => {
-                self.regs[0xF] = self.regs[x] & 1;
-                self.regs[x] >>= 1
+            SHR { x, y } => {
+                let value = self.regs[if self.quirks.shift_vx { x } else { y }];
+                self.regs[x] = value >> 1;
+                self.regs[0xF] = value & 1
             }
             SKNP { x } => {
                 if self.keypad & (1 << self.regs[x]) == 0 {
-                    self.pc += 2
+                    self.skip()
                 }
             }
             SKP { x } => {
                 if self.keypad & (1 << self.regs[x]) != 0 {
-                    self.pc += 2
+                    self.skip()
                 }
             }
             SNExkk { x, kk } => {
                 if self.regs[x] != kk {
-                    self.pc += 2
+                    self.skip()
                 }
             }
             SNExy { x, y } => {
                 if self.regs[x] != self.regs[y] {
-                    self.pc += 2
+                    self.skip()
                 }
             }
             SUB { x, y } => {
@@ -209,96 +533,209 @@ impl Chip8 {
                 self.regs[x] = new;
                 self.regs[0xF] = !borrow as u8
             }
-            XOR { x, y } => self.regs[x] ^= self.regs[y],
+            XOR { x, y } => {
+                self.regs[x] ^= self.regs[y];
+                self.reset_vf()
+            }
         }
     }
 
-    fn draw(&mut self, x: usize, y: usize, n: u8) -> bool {
-        let mut collision = false;
+    fn reset_vf(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.regs[0xF] = 0
+        }
+    }
 
-        for yoffset in 0..(n as usize) {
-            for xoffset in 0..8 {
-                if (self.mem[self.i as usize + yoffset] >> (7 - xoffset) & 0x01) == 1 {
-                    let x = self.regs[x] as usize + xoffset;
-                    let y = self.regs[y] as usize + yoffset;
+    // Stops at the instruction being executed, which is two bytes long like
+    // all those that can halt.
+    fn halt(&mut self, halt: Halt) {
+        self.pc = self.pc.wrapping_sub(2);
+        self.state = State::Halted { pc: self.pc, halt };
+    }
 
-                    if self.super_mode {
-                        collision = collision | self.toggle_pixel(x, y);
-                    } else {
-                        collision = collision | self.toggle_pixel(x * 2, y * 2);
-                        self.toggle_pixel(x * 2, y * 2 + 1);
-                        self.toggle_pixel(x * 2 + 1, y * 2);
-                        self.toggle_pixel(x * 2 + 1, y * 2 + 1);
-                    }
-                }
-            }
+    // After `LD [I], Vx` or `LD Vx, [I]`.
+    fn advance_i(&mut self, x: usize) {
+        if !self.quirks.load_store_keeps_i {
+            self.i = self.i.wrapping_add(x as u16 + 1)
         }
+    }
+
+    // Costs are derived from the VIP interpreter routines: a fixed fetch and
+    // decode overhead plus the work of the instruction itself. The table
+    // follows Jackson Sommerich's "Chip-8 Instruction Scheduling and
+    // Frequency" (2019), which counted them from the interpreter's listing as
+    // annotated in Laurence Scotford's "Chip-8 on the COSMAC VIP".
+    //
+    // Must be called before the instruction is executed, as skips and sprite
+    // alignment depend on the current register values.
+    fn vip_cycles(&self, instruction: &Instruction) -> u32 {
+        use self::Instruction::*;
+
+        let skip = |taken: bool| if taken { 2 } else { 0 };
+        let page_cross = |base: u16, offset: u16| if (base & 0xFF) + offset > 0xFF { 4 } else { 0 };
 
-        collision
+        match *instruction {
+            ADDix { x } => 16 + page_cross(self.i, self.regs[x] as u16),
+            ADDxkk { .. } | LDxt { .. } | LDtx { .. } | LDsx { .. } => 10,
+            ADDxy { .. } | AND { .. } | LDxy { .. } | OR { .. } | SHL { .. } | SHR { .. } => 44,
+            SUB { .. } | SUBN { .. } | XOR { .. } => 44,
+            CALL { .. } => 26,
+            CLS => 24 + 3078,
+            DRW { x, n, .. } => {
+                let row = if self.regs[x] & 0x07 == 0 { 34 } else { 46 };
+                26 + row * n as u32
+            }
+            DRWH { x, .. } => {
+                let row = if self.regs[x] & 0x07 == 0 { 46 } else { 58 };
+                26 + row * self.large_sprite_size().1 as u32
+            }
+            EXIT | INVALID { .. } => 0,
+            HIGH | LOW | PLANE { .. } => 24,
+            JPnnn { .. } | RET => 23,
+            JPnnnv { nnn } => 22 + page_cross(nnn, self.regs[0] as u16),
+            LDbx { x } => {
+                let digits = Self::get_bcd(self.regs[x]);
+                84 + 16 * (digits[0] + digits[1] + digits[2]) as u32
+            }
+            LDfx { .. } | LDhfx { .. } => 20,
+            LDix { x } | LDxi { x } | LDrx { x } | LDxr { x } => 14 + 14 * (x as u32 + 1),
+            // Not a VIP instruction, costed like `LD I, nnn`.
+            LDnnn { .. } | LDnnnn { .. } => 12,
+            LDx { .. } => 18,
+            LDxkk { .. } => 6,
+            RND { .. } => 36,
+            SCDn { n } => 24 + 16 * n as u32,
+            SCL | SCR => 24 + 64 * 4,
+            SExkk { x, kk } => 10 + skip(self.regs[x] == kk),
+            SNExkk { x, kk } => 10 + skip(self.regs[x] != kk),
+            SExy { x, y } => 16 + skip(self.regs[x] == self.regs[y]),
+            SNExy { x, y } => 16 + skip(self.regs[x] != self.regs[y]),
+            SKP { x } => 16 + skip(self.keypad & (1 << self.regs[x]) != 0),
+            SKNP { x } => 16 + skip(self.keypad & (1 << self.regs[x]) == 0),
+        }
     }
 
-    fn draw_16(&mut self, x: usize, y: usize) -> bool {
-        if !self.super_mode {
-            return false;
+    // Draws a sprite `width` pixels wide and `height` rows high from memory at
+    // I, returning the value for VF. With both XO-CHIP planes selected, the
+    // sprite for the second plane follows the one for the first.
+    fn draw(&mut self, x: usize, y: usize, width: usize, height: usize) -> u8 {
+        let (display_width, display_height) = self.display.get_size();
+        let x = self.regs[x] as usize % display_width;
+        let y = self.regs[y] as usize % display_height;
+        let count_rows = self.quirks.collision_rows && self.display.get_resolution() == Resolution::High;
+
+        let planes = self.display.get_planes();
+        let mut address = self.i as usize;
+        let mut collisions = 0;
+        for &plane in PLANES.iter().filter(|&&plane| planes & plane != 0) {
+            collisions = collisions.max(self.draw_plane(plane, address, (x, y), width, height, count_rows));
+            address += height * width / 8;
         }
 
-        let mut collision = false;
+        if count_rows {
+            collisions as u8
+        } else {
+            (collisions > 0) as u8
+        }
+    }
+
+    // Draws one plane of a sprite at `x`, `y` from `address`, returning the
+    // number of rows that collided, and with `count_rows` those clipped at
+    // the bottom too.
+    fn draw_plane(&mut self, plane: u8, address: usize, (x, y): (usize, usize), width: usize, height: usize, count_rows: bool) -> usize {
+        let (display_width, display_height) = self.display.get_size();
+        let mut collisions = 0;
+
+        for yoffset in 0..height {
+            if self.quirks.clip_sprites && y + yoffset >= display_height {
+                if count_rows {
+                    collisions += height - yoffset;
+                }
+                break;
+            }
+
+            let mut collision = false;
+
+            for xoffset in 0..width {
+                if self.quirks.clip_sprites && x + xoffset >= display_width {
+                    break;
+                }
 
-        for yoffset in 0..16 {
-            for xoffset in 0..16 {
-                if (self.mem[self.i as usize + yoffset * 2 + (xoffset >> 3)]
+                if (self.read_mem(address + yoffset * (width / 8) + (xoffset >> 3))
                     >> (7 - (xoffset & 0x07))
                     & 0x01)
                     == 1
                 {
-                    let x = self.regs[x] as usize + xoffset;
-                    let y = self.regs[y] as usize + yoffset;
+                    let x = (x + xoffset) % display_width;
+                    let y = (y + yoffset) % display_height;
 
-                    collision = collision | self.toggle_pixel(x, y);
+                    collision |= self.display.toggle_plane(plane, x, y);
                 }
             }
+
+            collisions += collision as usize;
         }
 
-        collision
+        collisions
     }
 
-    fn toggle_pixel(&mut self, x: usize, y: usize) -> bool {
-        let pixel = &mut self.display[offset(x, y)];
-        *pixel ^= true;
-        !*pixel
+    // Scroll amounts are given in high resolution pixels, in low resolution
+    // mode they are doubled unless the SCHIP 1.1 behaviour is requested.
+    fn scroll_scale(&self) -> usize {
+        if self.quirks.lores_scroll_halved {
+            1
+        } else {
+            self.display.get_resolution().scale()
+        }
     }
 
-    fn scroll_down(&mut self, n: u8) {
-        self.display.rotate_right(WIDTH * n as usize);
-        for pixel in &mut self.display[0..(WIDTH * n as usize)] {
-            *pixel = false;
+    fn check_keypad(&self) -> Option<u8> {
+        let key = self.keypad.trailing_zeros() as u8;
+        if key & 0xF == key {
+            Some(key & 0xF)
+        } else {
+            None
         }
     }
 
-    fn scroll_left(&mut self) {
-        self.display.rotate_left(4);
-        for y in 0..HEIGHT {
-            for x in (WIDTH - 4)..WIDTH {
-                self.display[offset(x, y)] = false;
-            }
+    // Reads by the program through I, which wrap around at the end of
+    // memory like the PC.
+    fn read_mem(&self, address: usize) -> u8 {
+        self.mem[address & (self.mem.len() - 1)]
+    }
+
+    // Every write by the program goes through here, wrapping around at the
+    // end of memory.
+    fn write_mem(&mut self, address: usize, value: u8) {
+        let address = address & (self.mem.len() - 1);
+        self.mem[address] = value;
+        self.invalidate(address);
+        self.recent_writes.retain(|&written| written != address);
+        if self.recent_writes.len() == RECENT_WRITES {
+            self.recent_writes.pop_front();
+        }
+        self.recent_writes.push_back(address);
+
+        // Writes happen while executing, after the PC moved past the writer.
+        let writer = self.pc.wrapping_sub(2);
+        if self.executed[address] {
+            self.push_code_write(CodeWrite { pc: writer, address, ran_before: true });
+        } else {
+            self.written_by.insert(address, writer);
         }
     }
 
-    fn scroll_right(&mut self) {
-        self.display.rotate_right(4);
-        for y in 0..HEIGHT {
-            for x in 0..4 {
-                self.display[offset(x, y)] = false;
-            }
+    // Forgets the instructions decoded from a byte, starting at it or up to
+    // three bytes before for `LD I, long`.
+    fn invalidate(&mut self, address: usize) {
+        for decoded in self.decoded.iter_mut().take(address + 1).skip(address.saturating_sub(3)) {
+            *decoded = None;
         }
     }
 
-    fn check_keypad(&self) -> Option<u8> {
-        let key = self.keypad.trailing_zeros() as u8;
-        if key & 0xF == key {
-            Some(key & 0xF)
-        } else {
-            None
+    fn push_code_write(&mut self, write: CodeWrite) {
+        if self.code_writes.len() < MAX_CODE_WRITES {
+            self.code_writes.push(write);
         }
     }
 
@@ -322,7 +759,7 @@ impl std::fmt::Debug for Chip8 {
         for col in 0..DEBUG_MEMORY_ROW_SIZE {
             write!(f, "{:02x} ", col)?;
         }
-        write!(f, "\n")?;
+        writeln!(f)?;
 
         for (index, row) in self.mem.chunks(DEBUG_MEMORY_ROW_SIZE).enumerate() {
             write!(f, "    {:03x}  ", index * DEBUG_MEMORY_ROW_SIZE)?;
@@ -331,7 +768,7 @@ impl std::fmt::Debug for Chip8 {
                 write!(f, "{:02x} ", byte)?;
             }
 
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
 
         Ok(())
@@ -339,13 +776,32 @@ impl std::fmt::Debug for Chip8 {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
     use super::Instruction::*;
     use super::*;
+    use display::{PLANE_1, PLANE_2, WIDTH};
+
+    // A ROM to start from when a test sets up memory and registers itself.
+    const CLS: [u8; 2] = [0x00, 0xE0];
+
+    #[test]
+    fn load_rom() {
+        assert_eq!(Chip8::new(&[]).err(), Some(RomError::Empty));
+
+        let rom = vec![0; 0x1000 - 0x200 + 1];
+        let error = Chip8::with_quirks(&rom, Platform::Chip8.quirks()).err();
+        assert_eq!(error, Some(RomError::TooLarge { size: 0xE01, max: 0xE00 }));
+        assert!(Chip8::with_quirks(&rom, Platform::XoChip.quirks()).is_ok());
+        assert!(Chip8::with_quirks(&rom[1..], Platform::Chip8.quirks()).is_ok());
+
+        assert!(rom_warning(&rom).is_some());
+        assert!(rom_warning(&rom[1..]).is_none());
+    }
 
     #[test]
     fn apply_keypad_value() {
-        let mut chip8 = Chip8::new(&[]);
+        let mut chip8 = Chip8::new(&CLS).unwrap();
         assert_eq!(chip8.keypad, 0b0000_0000);
         chip8.apply_keypad_value(0x0, true);
         assert_eq!(chip8.keypad, 0b0000_0001);
@@ -381,20 +837,241 @@ mod test {
     //     assert_eq!(chip8.regs[0xf], 1);
     // }
 
+    #[test]
+    fn execute_ldx_waits_for_release() {
+        // LD V3, K; JP 0x202
+        let mut chip8 = Chip8::new(&[0xF3, 0x0A, 0x12, 0x02]).unwrap();
+        chip8.tick();
+        assert_eq!(chip8.get_state(), State::WaitingForKey { x: 3, key: None });
+        chip8.tick();
+        assert_eq!(chip8.pc, 0x202);
+
+        chip8.apply_keypad_value(0x7, true);
+        chip8.apply_keypad_value(0x9, true);
+        assert_eq!(chip8.get_state(), State::WaitingForKey { x: 3, key: Some(0x7) });
+        chip8.apply_keypad_value(0x9, false);
+        assert_eq!(chip8.get_state(), State::WaitingForKey { x: 3, key: Some(0x7) });
+        chip8.apply_keypad_value(0x7, false);
+        assert_eq!(chip8.get_state(), State::Running);
+        assert_eq!(chip8.regs[3], 0x7);
+    }
+
+    #[test]
+    fn process_key_events() {
+        let mut chip8 = Chip8::new(&CLS).unwrap();
+        chip8.push_key_event(0x1, true);
+        chip8.push_key_event(0x2, true);
+        chip8.push_key_event(0x1, false);
+        chip8.push_key_event(0x1, true);
+
+        chip8.process_key_events();
+        assert_eq!(chip8.keypad, 0b0000_0110);
+        chip8.process_key_events();
+        assert_eq!(chip8.keypad, 0b0000_0110);
+        assert!(chip8.key_events.is_empty());
+    }
+
     #[test]
     fn execute_ldbx() {
-        let mut chip8 = Chip8::new(&[]);
+        let mut chip8 = Chip8::new(&CLS).unwrap();
         chip8.regs[0] = 123;
         chip8.i = 456;
         chip8.execute(&LDbx { x: 0 });
         assert_eq!(chip8.mem[456], 1);
         assert_eq!(chip8.mem[457], 2);
         assert_eq!(chip8.mem[458], 3);
+        assert_eq!(chip8.get_recent_writes(), &[456, 457, 458]);
+    }
+
+    #[test]
+    fn long_i() {
+        // LD I, long 0x1234; SE V0, 0; LD I, long 0x0300; LD V1, 1;
+        // LD V0, [I].
+        let rom = [0xF0, 0x00, 0x12, 0x34, 0x30, 0x00, 0xF0, 0x00, 0x03, 0x00, 0x61, 0x01, 0xF0, 0x65];
+        let mut chip8 = Chip8::new(&rom).unwrap();
+        chip8.set_memory(0x1234, 0x55);
+
+        assert_eq!(chip8.tick().to_string(), "LD I, long 0x1234");
+        assert_eq!(chip8.get_pc(), 0x204);
+        chip8.tick();
+        assert_eq!(chip8.get_pc(), 0x20A);
+        chip8.tick();
+        chip8.tick();
+        assert_eq!(chip8.get_i(), 0x1235);
+        assert_eq!((chip8.regs[0], chip8.regs[1]), (0x55, 1));
+    }
+
+    #[test]
+    fn stack_overflow() {
+        // CALL 0x200
+        let mut chip8 = Chip8::new(&[0x22, 0x00]).unwrap();
+        for _ in 0..STACK_SIZE {
+            chip8.tick();
+        }
+        assert_eq!(chip8.get_state(), State::Running);
+        chip8.tick();
+        assert_eq!(chip8.get_state(), State::Halted { pc: 0x200, halt: Halt::StackOverflow });
+        assert!(matches!(chip8.tick(), CALL { nnn: 0x200 }));
+        assert_eq!(chip8.sp as usize, STACK_SIZE);
+    }
+
+    #[test]
+    fn stack_underflow() {
+        // CALL 0x204; RET; RET
+        let mut chip8 = Chip8::new(&[0x22, 0x04, 0x00, 0xEE, 0x00, 0xEE]).unwrap();
+        chip8.tick();
+        chip8.tick();
+        assert_eq!((chip8.get_pc(), chip8.get_state()), (0x202, State::Running));
+        chip8.tick();
+        assert_eq!(chip8.get_state(), State::Halted { pc: 0x202, halt: Halt::StackUnderflow });
+        assert_eq!(chip8.get_pc(), 0x202);
+    }
+
+    #[test]
+    fn unsupported_halts() {
+        let mut chip8 = Chip8::new(&[0x00, 0x00]).unwrap();
+        chip8.tick();
+        assert_eq!(chip8.get_state(), State::Halted { pc: 0x200, halt: Halt::Unsupported });
+    }
+
+    // Memory accessed through I wraps around at the end, as does the PC.
+    #[test]
+    fn memory_wraps() {
+        let mut chip8 = Chip8::with_quirks(&CLS, Platform::Chip8.quirks()).unwrap();
+        chip8.i = 0xFFFE;
+        chip8.regs[0] = 0x03;
+        chip8.execute(&ADDix { x: 0 });
+        assert_eq!(chip8.i, 0x0001);
+
+        chip8.i = 0xFFF;
+        chip8.regs[1] = 0x81;
+        chip8.execute(&LDix { x: 1 });
+        assert_eq!((chip8.mem[0xFFF], chip8.mem[0x000]), (0x03, 0x81));
+        assert_eq!(chip8.i, 0x1001);
+        chip8.i = 0xFFF;
+        chip8.execute(&LDbx { x: 1 });
+        assert_eq!(chip8.mem[0x000..0x002], [0x02, 0x09]);
+        chip8.i = 0xFFF;
+        chip8.execute(&LDxi { x: 1 });
+        assert_eq!(chip8.regs[..2], [0x01, 0x02]);
+        chip8.i = 0xFFF;
+        chip8.execute(&DRW { x: 2, y: 2, n: 2 });
+        assert!(chip8.display.toggle(7, 0) && chip8.display.toggle(6, 1));
+
+        chip8.regs[0] = 0xFF;
+        chip8.execute(&JPnnnv { nnn: 0xFFF });
+        chip8.mem[0x0FE..0x100].copy_from_slice(&CLS);
+        assert!(matches!(chip8.tick(), Instruction::CLS));
+        assert_eq!(chip8.get_pc(), 0x100);
+    }
+
+    #[test]
+    fn octo_quirks() {
+        let mut quirks = Platform::XoChip.quirks();
+        for setting in &["jump_vx", "logic_resets_vf"] {
+            quirks.apply(setting).unwrap();
+        }
+        let mut chip8 = Chip8::with_quirks(&CLS, quirks).unwrap();
+
+        chip8.regs[1] = 0x81;
+        chip8.execute(&SHL { x: 0, y: 1 });
+        assert_eq!((chip8.regs[0], chip8.regs[0xF]), (0x02, 1));
+        chip8.execute(&SHR { x: 0, y: 1 });
+        assert_eq!((chip8.regs[0], chip8.regs[0xF]), (0x40, 1));
+
+        chip8.execute(&OR { x: 0, y: 1 });
+        assert_eq!((chip8.regs[0], chip8.regs[0xF]), (0xC1, 0));
+
+        chip8.i = 0x300;
+        chip8.execute(&LDix { x: 1 });
+        assert_eq!(chip8.i, 0x302);
+        chip8.execute(&LDxi { x: 0 });
+        assert_eq!(chip8.i, 0x303);
+
+        chip8.execute(&JPnnnv { nnn: 0x120 });
+        assert_eq!(chip8.pc, 0x120 + 0x81);
+    }
+
+    #[test]
+    fn vip_quirks() {
+        let mut chip8 = Chip8::with_quirks(&CLS, Platform::Chip8.quirks()).unwrap();
+        chip8.regs[1] = 0x81;
+        chip8.execute(&SHR { x: 0, y: 1 });
+        assert_eq!((chip8.regs[0], chip8.regs[0xF]), (0x40, 1));
+        chip8.execute(&OR { x: 0, y: 1 });
+        assert_eq!((chip8.regs[0], chip8.regs[0xF]), (0xC1, 0));
+
+        chip8.i = 0x300;
+        chip8.execute(&LDix { x: 1 });
+        assert_eq!(chip8.i, 0x302);
+    }
+
+    #[test]
+    fn recent_writes() {
+        let mut chip8 = Chip8::new(&CLS).unwrap();
+        chip8.i = 0x300;
+        chip8.execute(&LDix { x: 1 });
+        chip8.i = 0x300;
+        chip8.execute(&LDix { x: 0 });
+        assert_eq!(chip8.get_recent_writes(), &[0x301, 0x300]);
+
+        chip8.i = 0x400;
+        chip8.execute(&LDix { x: 0xF });
+        chip8.i = 0x500;
+        chip8.execute(&LDix { x: 0xF });
+        assert_eq!(chip8.get_recent_writes().len(), RECENT_WRITES);
+        assert_eq!(chip8.get_recent_writes().front(), Some(&0x400));
+    }
+
+    #[test]
+    fn code_writes() {
+        // LD I, 0x208; LD [I], V0; LD I, 0x200; LD [I], V0; CLS
+        let mut chip8 = Chip8::new(&[0xA2, 0x08, 0xF0, 0x55, 0xA2, 0x00, 0xF0, 0x55, 0x00, 0xE0]).unwrap();
+        for _ in 0..5 {
+            chip8.tick();
+        }
+        assert_eq!(
+            chip8.take_code_writes(),
+            [CodeWrite { pc: 0x206, address: 0x200, ran_before: true }, CodeWrite { pc: 0x202, address: 0x208, ran_before: false }]
+        );
+        assert!(chip8.take_code_writes().is_empty());
+        assert_eq!(CodeWrite { pc: 0x206, address: 0x200, ran_before: true }.to_string(), "206 wrote over code at 200, which already ran");
+    }
+
+    #[test]
+    fn decode_cache() {
+        // CLS; JP 0x200
+        let mut chip8 = Chip8::new(&[0x00, 0xE0, 0x12, 0x00]).unwrap();
+        chip8.tick();
+        chip8.tick();
+        assert!(chip8.decoded[0x200].is_some());
+
+        // Both bytes of an instruction decode it again.
+        chip8.set_memory(0x201, 0xFF);
+        assert!(chip8.decoded[0x200].is_none());
+        assert!(matches!(chip8.tick(), Instruction::HIGH));
+        chip8.pc = 0x200;
+        chip8.i = 0x200;
+        chip8.regs[0] = 0x12;
+        chip8.execute(&LDix { x: 0 });
+        assert!(matches!(chip8.tick(), Instruction::JPnnn { nnn: 0x2FF }));
+
+        // Code past the program runs without being cached, and writes over
+        // it are still noticed.
+        assert_eq!(chip8.decoded.len(), 0x204);
+        chip8.take_code_writes();
+        chip8.set_memory(0x2FF, 0x00);
+        chip8.set_memory(0x300, 0xE0);
+        assert!(matches!(chip8.tick(), Instruction::CLS));
+        chip8.pc = 0x300;
+        chip8.i = 0x2FF;
+        chip8.execute(&LDix { x: 0 });
+        assert_eq!(chip8.take_code_writes(), [CodeWrite { pc: 0x2FE, address: 0x2FF, ran_before: true }]);
     }
 
     #[test]
     fn check_keypad() {
-        let mut chip8 = Chip8::new(&[]);
+        let mut chip8 = Chip8::new(&CLS).unwrap();
         chip8.keypad = 0b0000_0000_0000_0000u16;
         assert_eq!(chip8.check_keypad(), None);
         chip8.keypad = 0b0000_0000_0000_0001u16;
@@ -409,7 +1086,8 @@ mod test {
 
     #[test]
     fn execute_drw() {
-        let mut chip8 = Chip8::new(&[]);
+        let mut chip8 = Chip8::new(&CLS).unwrap();
+        chip8.display.set_resolution(Resolution::High);
         chip8.regs[0] = 2;
         chip8.regs[1] = 3;
         chip8.i = 456;
@@ -418,37 +1096,130 @@ mod test {
         chip8.mem[458] = 0b10000001;
         chip8.execute(&DRW { x: 0, y: 1, n: 3 });
 
-        assert_eq!(chip8.display[3 * WIDTH + 2], false);
-        assert_eq!(chip8.display[3 * WIDTH + 3], false);
-        assert_eq!(chip8.display[3 * WIDTH + 4], true);
-        assert_eq!(chip8.display[3 * WIDTH + 5], true);
-        assert_eq!(chip8.display[3 * WIDTH + 6], true);
-        assert_eq!(chip8.display[3 * WIDTH + 7], true);
-        assert_eq!(chip8.display[3 * WIDTH + 8], false);
-        assert_eq!(chip8.display[3 * WIDTH + 9], false);
-
-        assert_eq!(chip8.display[4 * WIDTH + 2], false);
-        assert_eq!(chip8.display[4 * WIDTH + 3], true);
-        assert_eq!(chip8.display[4 * WIDTH + 4], false);
-        assert_eq!(chip8.display[4 * WIDTH + 5], false);
-        assert_eq!(chip8.display[4 * WIDTH + 6], false);
-        assert_eq!(chip8.display[4 * WIDTH + 7], false);
-        assert_eq!(chip8.display[4 * WIDTH + 8], true);
-        assert_eq!(chip8.display[4 * WIDTH + 9], false);
-
-        assert_eq!(chip8.display[5 * WIDTH + 2], true);
-        assert_eq!(chip8.display[5 * WIDTH + 3], false);
-        assert_eq!(chip8.display[5 * WIDTH + 4], false);
-        assert_eq!(chip8.display[5 * WIDTH + 5], false);
-        assert_eq!(chip8.display[5 * WIDTH + 6], false);
-        assert_eq!(chip8.display[5 * WIDTH + 7], false);
-        assert_eq!(chip8.display[5 * WIDTH + 8], false);
-        assert_eq!(chip8.display[5 * WIDTH + 9], true);
+        assert_eq!(chip8.display.get_pixels()[3 * WIDTH + 2], 0);
+        assert_eq!(chip8.display.get_pixels()[3 * WIDTH + 3], 0);
+        assert_eq!(chip8.display.get_pixels()[3 * WIDTH + 4], 1);
+        assert_eq!(chip8.display.get_pixels()[3 * WIDTH + 5], 1);
+        assert_eq!(chip8.display.get_pixels()[3 * WIDTH + 6], 1);
+        assert_eq!(chip8.display.get_pixels()[3 * WIDTH + 7], 1);
+        assert_eq!(chip8.display.get_pixels()[3 * WIDTH + 8], 0);
+        assert_eq!(chip8.display.get_pixels()[3 * WIDTH + 9], 0);
+
+        assert_eq!(chip8.display.get_pixels()[4 * WIDTH + 2], 0);
+        assert_eq!(chip8.display.get_pixels()[4 * WIDTH + 3], 1);
+        assert_eq!(chip8.display.get_pixels()[4 * WIDTH + 4], 0);
+        assert_eq!(chip8.display.get_pixels()[4 * WIDTH + 5], 0);
+        assert_eq!(chip8.display.get_pixels()[4 * WIDTH + 6], 0);
+        assert_eq!(chip8.display.get_pixels()[4 * WIDTH + 7], 0);
+        assert_eq!(chip8.display.get_pixels()[4 * WIDTH + 8], 1);
+        assert_eq!(chip8.display.get_pixels()[4 * WIDTH + 9], 0);
+
+        assert_eq!(chip8.display.get_pixels()[5 * WIDTH + 2], 1);
+        assert_eq!(chip8.display.get_pixels()[5 * WIDTH + 3], 0);
+        assert_eq!(chip8.display.get_pixels()[5 * WIDTH + 4], 0);
+        assert_eq!(chip8.display.get_pixels()[5 * WIDTH + 5], 0);
+        assert_eq!(chip8.display.get_pixels()[5 * WIDTH + 6], 0);
+        assert_eq!(chip8.display.get_pixels()[5 * WIDTH + 7], 0);
+        assert_eq!(chip8.display.get_pixels()[5 * WIDTH + 8], 0);
+        assert_eq!(chip8.display.get_pixels()[5 * WIDTH + 9], 1);
+    }
+
+    #[test]
+    fn execute_drw_planes() {
+        let mut chip8 = Chip8::new(&CLS).unwrap();
+        chip8.i = 0x300;
+        chip8.mem[0x300..0x302].copy_from_slice(&[0x80, 0x40]);
+
+        // The second plane's sprite follows the first's.
+        chip8.execute(&PLANE { n: 3 });
+        chip8.execute(&DRW { x: 0, y: 0, n: 1 });
+        assert_eq!((chip8.display.get_value(0, 0), chip8.display.get_value(1, 0)), (PLANE_1, PLANE_2));
+        assert_eq!(chip8.regs[0xF], 0);
+
+        chip8.execute(&PLANE { n: 2 });
+        chip8.execute(&DRW { x: 0, y: 0, n: 1 });
+        assert_eq!(chip8.display.get_value(0, 0), PLANE_1 | PLANE_2);
+        chip8.execute(&DRW { x: 0, y: 0, n: 1 });
+        assert_eq!((chip8.display.get_value(0, 0), chip8.regs[0xF]), (PLANE_1, 1));
+
+        chip8.execute(&PLANE { n: 0 });
+        chip8.execute(&DRW { x: 0, y: 0, n: 1 });
+        assert_eq!((chip8.display.get_value(0, 0), chip8.regs[0xF]), (PLANE_1, 0));
+    }
+
+    #[test]
+    fn execute_drwh_low_resolution() {
+        let mut chip8 = Chip8::new(&CLS).unwrap();
+        chip8.i = 0x300;
+        chip8.mem[0x300..0x320].copy_from_slice(&[0xFF; 32]);
+        chip8.execute(&DRWH { x: 0, y: 0 });
+        assert!(chip8.display.get_pixel(15, 15));
+        assert!(!chip8.display.get_pixel(16, 0));
+        chip8.execute(&DRWH { x: 0, y: 0 });
+        assert_eq!(chip8.regs[0xF], 1);
+
+        let mut chip8 = Chip8::with_quirks(&CLS, Platform::Schip.quirks()).unwrap();
+        chip8.i = 0x300;
+        chip8.mem[0x300..0x320].copy_from_slice(&[0xFF; 32]);
+        chip8.execute(&DRWH { x: 0, y: 0 });
+        assert!(chip8.display.get_pixel(7, 15));
+        assert!(!chip8.display.get_pixel(8, 0));
+
+        chip8.display.set_resolution(Resolution::High);
+        chip8.execute(&DRWH { x: 0, y: 0 });
+        assert!(chip8.display.get_pixel(15, 15));
+
+        // A sprite of no rows on the VIP.
+        let mut chip8 = Chip8::with_quirks(&CLS, Platform::Chip8.quirks()).unwrap();
+        chip8.i = 0x300;
+        chip8.mem[0x300..0x320].copy_from_slice(&[0xFF; 32]);
+        chip8.regs[0xF] = 1;
+        chip8.execute(&DRWH { x: 0, y: 0 });
+        assert!(!chip8.display.get_pixel(0, 0));
+        assert_eq!(chip8.regs[0xF], 0);
+        assert_eq!(chip8.vip_cycles(&DRWH { x: 0, y: 0 }), 26);
+    }
+
+    #[test]
+    fn vip_cycles() {
+        let mut chip8 = Chip8::new(&CLS).unwrap();
+        chip8.regs[0] = 8;
+        chip8.regs[1] = 3;
+        assert_eq!(chip8.vip_cycles(&DRW { x: 0, y: 1, n: 2 }), 26 + 34 * 2);
+        assert_eq!(chip8.vip_cycles(&DRW { x: 1, y: 0, n: 2 }), 26 + 46 * 2);
+        assert_eq!(chip8.vip_cycles(&SExkk { x: 1, kk: 3 }), 12);
+        assert_eq!(chip8.vip_cycles(&SExkk { x: 1, kk: 4 }), 10);
+        assert_eq!(chip8.vip_cycles(&LDbx { x: 0 }), 84 + 16 * 8);
+
+        // CLS
+        chip8.tick();
+        assert_eq!(chip8.get_cycles(), 0);
+        chip8.set_cycle_counting(true);
+        chip8.pc = 0x200;
+        chip8.tick();
+        assert_eq!(chip8.get_cycles(), 24 + 3078);
+    }
+
+    #[test]
+    fn execute_scroll_low_resolution() {
+        let mut chip8 = Chip8::new(&CLS).unwrap();
+        chip8.display.toggle(0, 0);
+        chip8.execute(&SCR);
+        assert!(chip8.display.get_pixel(4, 0));
+        chip8.execute(&SCDn { n: 2 });
+        assert!(chip8.display.get_pixel(4, 2));
+
+        let mut chip8 = Chip8::with_quirks(&CLS, Platform::Schip.quirks()).unwrap();
+        chip8.display.toggle(0, 0);
+        chip8.execute(&SCR);
+        assert!(chip8.display.get_pixel(2, 0));
+        chip8.execute(&SCDn { n: 2 });
+        assert!(chip8.display.get_pixel(2, 1));
     }
 
     #[test]
     fn execute_drw_collision() {
-        let mut chip8 = Chip8::new(&[]);
+        let mut chip8 = Chip8::new(&CLS).unwrap();
         chip8.regs[0] = 2;
         chip8.regs[1] = 3;
         chip8.i = 456;
@@ -460,4 +1231,50 @@ mod test {
         chip8.execute(&DRW { x: 0, y: 1, n: 1 });
         assert_eq!(chip8.regs[0xF], 1);
     }
+
+    #[test]
+    fn execute_drw_wrap() {
+        let mut chip8 = Chip8::new(&CLS).unwrap();
+        chip8.regs[0] = 62 + 64;
+        chip8.regs[1] = 31;
+        chip8.i = 456;
+        chip8.mem[456] = 0b1111_0000;
+        chip8.mem[457] = 0b1111_0000;
+        chip8.execute(&DRW { x: 0, y: 1, n: 2 });
+
+        assert!(chip8.display.get_pixel(63, 31));
+        assert!(chip8.display.get_pixel(1, 31));
+        assert!(chip8.display.get_pixel(62, 0));
+        assert!(chip8.display.get_pixel(1, 0));
+    }
+
+    #[test]
+    fn execute_drw_clip() {
+        let mut chip8 = Chip8::with_quirks(&CLS, Platform::Chip8.quirks()).unwrap();
+        chip8.regs[0] = 62 + 64;
+        chip8.regs[1] = 31;
+        chip8.i = 456;
+        chip8.mem[456] = 0b1111_0000;
+        chip8.mem[457] = 0b1111_0000;
+        chip8.execute(&DRW { x: 0, y: 1, n: 2 });
+
+        assert!(chip8.display.get_pixel(63, 31));
+        assert!(!chip8.display.get_pixel(1, 31));
+        assert!(!chip8.display.get_pixel(62, 0));
+    }
+
+    #[test]
+    fn execute_drw_collision_rows() {
+        let mut chip8 = Chip8::with_quirks(&CLS, Platform::Schip.quirks()).unwrap();
+        chip8.display.set_resolution(Resolution::High);
+        chip8.regs[0] = 0;
+        chip8.regs[1] = 60;
+        chip8.i = 456;
+        chip8.mem[456..462].copy_from_slice(&[0x80; 6]);
+        chip8.execute(&DRW { x: 0, y: 1, n: 1 });
+        assert_eq!(chip8.regs[0xF], 0);
+
+        chip8.execute(&DRW { x: 0, y: 1, n: 6 });
+        assert_eq!(chip8.regs[0xF], 1 + 2);
+    }
 }
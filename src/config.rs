@@ -0,0 +1,125 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// A minimal INI style configuration file:
+//
+//     # comment
+//     [section name]
+//     key = value
+//
+// Sections may appear more than once, later entries override earlier ones.
+#[derive(Debug, Default)]
+pub struct Config {
+    sections: Vec<Section>,
+}
+
+#[derive(Debug)]
+struct Section {
+    name: String,
+    entries: Vec<(String, String)>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let name = line[1..line.len() - 1].split_whitespace().collect::<Vec<_>>().join(" ");
+                config.sections.push(Section { name, entries: Vec::new() });
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if !key.trim().is_empty() => (key.trim(), value.trim()),
+                _ => return Err(format!("line {}: expected 'key = value', found '{}'", number + 1, line)),
+            };
+
+            match config.sections.last_mut() {
+                Some(section) => section.entries.push((key.to_lowercase(), value.to_string())),
+                None => return Err(format!("line {}: '{}' is not inside a [section]", number + 1, key)),
+            }
+        }
+
+        Ok(config)
+    }
+
+    // Loads a configuration file, a missing file is an empty configuration.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|error| format!("{}: {}", path.display(), error)),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(error) => Err(format!("{}: {}", path.display(), error)),
+        }
+    }
+
+    // All entries of the sections with the given name, in file order.
+    pub fn entries<'a>(&'a self, name: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.sections
+            .iter()
+            .filter(move |section| section.name.eq_ignore_ascii_case(name))
+            .flat_map(|section| section.entries.iter())
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn get<'a>(&'a self, name: &'a str, key: &str) -> Option<&'a str> {
+        self.entries(name).filter(|&(k, _)| k == key).map(|(_, value)| value).last()
+    }
+}
+
+// `$XDG_CONFIG_HOME/chip8`, falling back to `~/.config/chip8`.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("chip8"))
+}
+
+// `$XDG_DATA_HOME/chip8`, falling back to `~/.local/share/chip8`.
+pub fn data_dir() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share")))
+        .map(|dir| dir.join("chip8"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let config = Config::parse(
+            "# bindings\n\
+             [keys]\n\
+             Q = 4\n\
+             w = 5\n\
+             \n\
+             [keys  TETRIS]\n\
+             q = none\n\
+             [keys]\n\
+             q = 6\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.entries("keys").collect::<Vec<_>>(), vec![("q", "4"), ("w", "5"), ("q", "6")]);
+        assert_eq!(config.get("keys tetris", "q"), Some("none"));
+        assert_eq!(config.get("keys", "q"), Some("6"));
+        assert_eq!(config.get("other", "q"), None);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Config::parse("q = 4").is_err());
+        assert!(Config::parse("[keys]\nq").is_err());
+    }
+}
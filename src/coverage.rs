@@ -0,0 +1,192 @@
+use chip8::{Chip8, ROM_OFFSET};
+use image::{Rgba, RgbaImage};
+use instruction::Instruction;
+use std::fmt::Write;
+use std::ops::Range;
+
+// Bytes in a row of the heatmap, and the size of a byte in image pixels.
+const HEATMAP_COLUMNS: usize = 64;
+const HEATMAP_SCALE: u32 = 8;
+const UNTOUCHED: [u8; 4] = [32, 32, 32, 255];
+// The heatmap shows at least the memory of the original CHIP-8, and beyond
+// that only up to the last byte used.
+const MIN_HEATMAP_SIZE: usize = 0x1000;
+
+// Counts how often every byte of memory was fetched as part of an
+// instruction, read as data by draws and `LD Vx, [I]`, and written by
+// `LD [I], Vx` and `LD B, Vx`.
+pub struct Coverage {
+    fetched: Vec<u32>,
+    read: Vec<u32>,
+    written: Vec<u32>,
+}
+
+impl Coverage {
+    pub fn new(memory_size: usize) -> Self {
+        Coverage {
+            fetched: vec![0; memory_size],
+            read: vec![0; memory_size],
+            written: vec![0; memory_size],
+        }
+    }
+
+    // Records an instruction that was just run from `pc`. Only `LD [I], Vx`
+    // and `LD Vx, [I]` may have moved I past what they used.
+    pub fn record(&mut self, chip8: &Chip8, pc: u16, instruction: &Instruction) {
+        let pc = pc as usize;
+        count(&mut self.fetched, pc..pc + instruction.size() as usize);
+
+        let i = chip8.get_i() as usize;
+        let before = |x: usize| if chip8.get_quirks().load_store_keeps_i { i } else { i.wrapping_sub(x + 1) & 0xFFFF };
+        // A sprite for each selected plane.
+        let planes = chip8.get_display().get_planes().count_ones() as usize;
+        match *instruction {
+            Instruction::DRW { n, .. } => count(&mut self.read, i..i + planes * n as usize),
+            Instruction::DRWH { .. } => {
+                let (width, height) = chip8.large_sprite_size();
+                count(&mut self.read, i..i + planes * width / 8 * height)
+            }
+            Instruction::LDxi { x } => count(&mut self.read, before(x)..before(x) + x + 1),
+            Instruction::LDix { x } => count(&mut self.written, before(x)..before(x) + x + 1),
+            Instruction::LDbx { .. } => count(&mut self.written, i..i + 3),
+            _ => (),
+        }
+    }
+
+    fn used(&self, address: usize) -> bool {
+        self.fetched[address] > 0 || self.read[address] > 0 || self.written[address] > 0
+    }
+
+    // Memory as a grid of 64 bytes to a row, each byte green when it was run
+    // as code, blue when read and red when written, mixing when it was more
+    // than one, and brighter the more often that happened.
+    pub fn heatmap(&self) -> RgbaImage {
+        let size = self.fetched.len();
+        let end = (0..size).rev().find(|&address| self.used(address)).map_or(0, |last| last + 1).max(MIN_HEATMAP_SIZE.min(size));
+        let rows = end.div_ceil(HEATMAP_COLUMNS) as u32;
+        let mut image = RgbaImage::new(HEATMAP_COLUMNS as u32 * HEATMAP_SCALE, rows * HEATMAP_SCALE);
+        let (fetched, read, written) = (brightness(&self.fetched), brightness(&self.read), brightness(&self.written));
+
+        for address in 0..end {
+            let colour = match [written(address), fetched(address), read(address)] {
+                [0, 0, 0] => Rgba(UNTOUCHED),
+                [red, green, blue] => Rgba([red, green, blue, 255]),
+            };
+            let left = (address % HEATMAP_COLUMNS) as u32 * HEATMAP_SCALE;
+            let top = (address / HEATMAP_COLUMNS) as u32 * HEATMAP_SCALE;
+            for y in top..top + HEATMAP_SCALE {
+                for x in left..left + HEATMAP_SCALE {
+                    image.put_pixel(x, y, colour);
+                }
+            }
+        }
+        image
+    }
+
+    // A disassembly of the program from 0x200 to the last byte that is set
+    // or was used, with how often each line was run, read and written. Bytes
+    // that were used but never run are shown as data, and those that weren't
+    // used at all are disassembled two at a time with `-` for the counts.
+    pub fn annotate(&self, memory: &[u8]) -> String {
+        let end = (ROM_OFFSET..memory.len()).rev().find(|&address| memory[address] != 0 || self.used(address)).map_or(ROM_OFFSET, |last| last + 1);
+        let total = |counts: &[u32], range: Range<usize>| counts[range].iter().sum::<u32>();
+
+        let mut text = "address  bytes            run     read    write  instruction\n".to_string();
+        let mut address = ROM_OFFSET;
+        while address < end {
+            // Only `LD I, long` that ran is taken as four bytes.
+            let long = Instruction::decode(memory, address).filter(|instruction| instruction.size() == 4 && self.fetched[address] > 0);
+            let size = match long {
+                Some(_) => 4,
+                None if self.fetched[address] == 0 && self.used(address) => 1,
+                None => 2.min(memory.len() - address),
+            };
+            let range = address..address + size;
+            let bytes = memory[range.clone()].iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(" ");
+            let disassembly = match (long, size) {
+                (Some(instruction), _) => instruction.to_string(),
+                (None, 2) => Instruction::from_opcode(u16::from(memory[address]) << 8 | u16::from(memory[address + 1])).to_string(),
+                _ => format!("DB 0x{:02X}", memory[address]),
+            };
+
+            if range.clone().any(|address| self.used(address)) {
+                let run = total(&self.fetched, range.clone()) / size.max(2) as u32;
+                let (read, written) = (total(&self.read, range.clone()), total(&self.written, range));
+                writeln!(text, "{:03X}      {:<11} {:8} {:8} {:8}  {}", address, bytes, run, read, written, disassembly).unwrap();
+            } else {
+                writeln!(text, "{:03X}      {:<11} {:>8} {:>8} {:>8}  {}", address, bytes, "-", "-", "-", disassembly).unwrap();
+            }
+            address += size;
+        }
+        text
+    }
+}
+
+fn count(counts: &mut [u32], range: Range<usize>) {
+    let end = range.end.min(counts.len());
+    for count in &mut counts[range.start.min(end)..end] {
+        *count += 1;
+    }
+}
+
+// Scales counts logarithmically to the brightness of a colour channel, with
+// anything used at all at least a third bright.
+fn brightness(counts: &[u32]) -> impl Fn(usize) -> u8 + '_ {
+    let max = (f64::from(counts.iter().cloned().max().unwrap_or(0)) + 1.0).ln();
+    move |address| match counts[address] {
+        0 => 0,
+        count => (85.0 + 170.0 * (f64::from(count) + 1.0).ln() / max).round() as u8,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use platform::Platform;
+    use scheduler::{Scheduler, Timing};
+
+    // LD I, 0x20A; LD [I], V0; DRW V0, V0, 1; JP 0x204; CLS; then data.
+    const ROM: [u8; 11] = [0xA2, 0x0A, 0xF0, 0x55, 0xD0, 0x01, 0x12, 0x04, 0x00, 0xE0, 0x80];
+
+    // SCHIP keeps I on storing, so the data byte is both written and drawn.
+    fn run() -> (Chip8, Coverage) {
+        let mut chip8 = Chip8::with_quirks(&ROM, Platform::Schip.quirks()).unwrap();
+        let mut coverage = Coverage::new(chip8.get_memory().len());
+        let mut scheduler = Scheduler::new(Timing::Instructions(420.0));
+        scheduler.run_frame_with(&mut chip8, |chip8, pc, instruction| {
+            coverage.record(chip8, pc, instruction);
+            true
+        });
+        (chip8, coverage)
+    }
+
+    #[test]
+    fn annotate() {
+        let (chip8, coverage) = run();
+        let text = coverage.annotate(chip8.get_memory());
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[1..],
+            [
+                "200      A2 0A              1        0        0  LD I, 0x20A",
+                "202      F0 55              1        0        0  LD [I], V0",
+                "204      D0 01              3        0        0  DRW V0, V0, 1",
+                "206      12 04              2        0        0  JP 0x204",
+                "208      00 E0              -        -        -  CLS",
+                "20A      00                 0        3        1  DB 0x00",
+            ]
+        );
+    }
+
+    #[test]
+    fn heatmap() {
+        let (_, coverage) = run();
+        let image = coverage.heatmap();
+        assert_eq!(image.dimensions(), (64 * HEATMAP_SCALE, 4096 / 64 * HEATMAP_SCALE));
+
+        let pixel = |address: u32| *image.get_pixel(address % 64 * HEATMAP_SCALE, address / 64 * HEATMAP_SCALE);
+        assert_eq!(pixel(0x000), Rgba(UNTOUCHED));
+        assert_eq!(pixel(0x204), Rgba([0, 255, 0, 255]));
+        assert_eq!(pixel(0x20A), Rgba([255, 0, 255, 255]));
+    }
+}
@@ -0,0 +1,106 @@
+use config::Config;
+use platform::Platform;
+use sha1::sha1_hex;
+use std::path::Path;
+
+const BUNDLED: &str = include_str!("../roms.cfg");
+
+// What's known about a ROM. Every field is optional, missing ones fall back
+// to the command line and the built-in defaults.
+#[derive(Clone, Debug, Default)]
+pub struct RomInfo {
+    pub hash: String,
+    pub name: Option<String>,
+    pub platform: Option<Platform>,
+    pub hz: Option<f64>,
+    pub quirks: Vec<String>,
+    pub palette: Option<String>,
+    pub keys: Vec<(String, String)>,
+}
+
+// ROM settings keyed by the SHA-1 of the ROM bytes, one section per ROM:
+//
+//     [6f6509f38220e057a7e32ebb22dd353c1078e3e7]
+//     name = Blitz
+//     platform = chip8
+//     hz = 500
+//     quirks = clip_sprites, collision_rows=false
+//     palette = amber
+//     key.left = 4
+//
+// The bundled database is extended and overridden by `roms.cfg` in the
+// configuration directory.
+pub struct Database {
+    configs: Vec<Config>,
+}
+
+impl Database {
+    pub fn bundled() -> Self {
+        Database {
+            configs: vec![Config::parse(BUNDLED).expect("bundled ROM database should be valid")],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut database = Self::bundled();
+        database.configs.push(Config::load(path)?);
+        Ok(database)
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Result<RomInfo, String> {
+        let mut info = RomInfo {
+            hash: sha1_hex(rom),
+            ..RomInfo::default()
+        };
+
+        for config in &self.configs {
+            for (key, value) in config.entries(&info.hash) {
+                match key {
+                    "name" => info.name = Some(value.to_string()),
+                    "platform" => info.platform = Some(value.parse()?),
+                    "hz" => info.hz = Some(value.parse().map_err(|_| format!("invalid hz '{}'", value))?),
+                    "quirks" => info
+                        .quirks
+                        .extend(value.split(',').map(str::trim).filter(|quirk| !quirk.is_empty()).map(String::from)),
+                    "palette" => info.palette = Some(value.to_string()),
+                    _ if key.starts_with("key.") => info.keys.push((key[4..].to_string(), value.to_string())),
+                    _ => return Err(format!("unknown setting '{}' for ROM {}", key, info.hash)),
+                }
+            }
+        }
+
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let database = Database {
+            configs: vec![
+                Config::parse("[a9993e364706816aba3e25717850c26c9cd0d89d]\nname = ABC\nhz = 1000\nquirks = clip_sprites\n").unwrap(),
+                Config::parse("[a9993e364706816aba3e25717850c26c9cd0d89d]\nplatform = schip\nkey.left = 4\n").unwrap(),
+            ],
+        };
+
+        let info = database.lookup(b"abc").unwrap();
+        assert_eq!(info.name, Some("ABC".to_string()));
+        assert_eq!(info.platform, Some(Platform::Schip));
+        assert_eq!(info.hz, Some(1000.0));
+        assert_eq!(info.quirks, vec!["clip_sprites".to_string()]);
+        assert_eq!(info.keys, vec![("left".to_string(), "4".to_string())]);
+
+        assert_eq!(database.lookup(b"abcd").unwrap().name, None);
+    }
+
+    #[test]
+    fn bundled() {
+        let rom = include_bytes!("../games/BLITZ");
+        let info = Database::bundled().lookup(rom).unwrap();
+        assert_eq!(info.name, Some("Blitz".to_string()));
+        assert_eq!(info.platform, Some(Platform::Chip8));
+    }
+}
@@ -0,0 +1,258 @@
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    Low,
+    High,
+}
+
+impl Resolution {
+    // Physical pixels per logical pixel along each axis.
+    pub fn scale(self) -> usize {
+        match self {
+            Resolution::Low => 2,
+            Resolution::High => 1,
+        }
+    }
+
+    pub fn size(self) -> (usize, usize) {
+        (WIDTH / self.scale(), HEIGHT / self.scale())
+    }
+}
+
+// Bit masks of the two XO-CHIP bit planes. Each pixel holds one bit per
+// plane, so its value picks one of the four palette colours.
+pub const PLANE_1: u8 = 1;
+pub const PLANE_2: u8 = 2;
+pub const PLANES: [u8; 2] = [PLANE_1, PLANE_2];
+
+// The framebuffer is always kept at the SCHIP high resolution of 128x64, with
+// low resolution pixels covering 2x2 physical pixels. All coordinates passed
+// in are logical, in the current resolution, except for scroll amounts which
+// are physical so the caller can pick the SCHIP or XO-CHIP semantics.
+//
+// Clearing and scrolling only affect the selected planes, as on XO-CHIP.
+// Only the first is selected to begin with, which is all other programs use.
+pub struct Display {
+    resolution: Resolution,
+    pixels: [u8; WIDTH * HEIGHT],
+    planes: u8,
+    // Set whenever a pixel may have changed, so frontends can skip redrawing
+    // frames in which nothing was drawn.
+    dirty: bool,
+}
+
+fn offset(x: usize, y: usize) -> usize {
+    (x % WIDTH) + ((y % HEIGHT) * WIDTH)
+}
+
+impl Display {
+    pub fn new() -> Self {
+        Display {
+            resolution: Resolution::Low,
+            pixels: [0; WIDTH * HEIGHT],
+            planes: PLANE_1,
+            dirty: true,
+        }
+    }
+
+    pub fn get_resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    // Switching resolution clears the screen, every plane of it, as both
+    // SCHIP and XO-CHIP games expect when issuing HIGH or LOW.
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+        self.pixels.copy_from_slice(&[0; WIDTH * HEIGHT]);
+        self.dirty = true;
+    }
+
+    pub fn get_planes(&self) -> u8 {
+        self.planes
+    }
+
+    // Selects the planes to draw to, clear and scroll, as a mask of
+    // `PLANE_1` and `PLANE_2`.
+    pub fn set_planes(&mut self, planes: u8) {
+        self.planes = planes & (PLANE_1 | PLANE_2);
+    }
+
+    pub fn get_size(&self) -> (usize, usize) {
+        self.resolution.size()
+    }
+
+    // The raw 128x64 framebuffer, with the planes set in each pixel.
+    pub fn get_pixels(&self) -> &[u8] {
+        &self.pixels[..]
+    }
+
+    // Whether a logical pixel is set in any plane.
+    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        self.get_value(x, y) != 0
+    }
+
+    // The planes set in a logical pixel.
+    pub fn get_value(&self, x: usize, y: usize) -> u8 {
+        let scale = self.resolution.scale();
+        self.pixels[offset(x * scale, y * scale)]
+    }
+
+    // Returns whether the display changed since the last call.
+    pub fn take_dirty(&mut self) -> bool {
+        let dirty = self.dirty;
+        self.dirty = false;
+        dirty
+    }
+
+    pub fn clear(&mut self) {
+        let planes = self.planes;
+        self.pixels.iter_mut().for_each(|pixel| *pixel &= !planes);
+        self.dirty = true;
+    }
+
+    // Flips a logical pixel in the first plane, returning whether it was
+    // turned off.
+    pub fn toggle(&mut self, x: usize, y: usize) -> bool {
+        self.toggle_plane(PLANE_1, x, y)
+    }
+
+    // Flips a logical pixel in one plane, returning whether it was turned
+    // off there.
+    pub fn toggle_plane(&mut self, plane: u8, x: usize, y: usize) -> bool {
+        if self.resolution == Resolution::High {
+            return self.toggle_physical(plane, x, y);
+        }
+
+        let collision = self.toggle_physical(plane, x * 2, y * 2);
+        self.toggle_physical(plane, x * 2, y * 2 + 1);
+        self.toggle_physical(plane, x * 2 + 1, y * 2);
+        self.toggle_physical(plane, x * 2 + 1, y * 2 + 1);
+        collision
+    }
+
+    pub fn scroll_down(&mut self, n: usize) {
+        self.scroll(|pixels| {
+            pixels.rotate_right(WIDTH * n);
+            for pixel in &mut pixels[0..(WIDTH * n)] {
+                *pixel = 0;
+            }
+        });
+    }
+
+    pub fn scroll_left(&mut self, n: usize) {
+        self.scroll(|pixels| {
+            for row in pixels.chunks_mut(WIDTH) {
+                row.rotate_left(n);
+                for pixel in &mut row[(WIDTH - n)..] {
+                    *pixel = 0;
+                }
+            }
+        });
+    }
+
+    pub fn scroll_right(&mut self, n: usize) {
+        self.scroll(|pixels| {
+            for row in pixels.chunks_mut(WIDTH) {
+                row.rotate_right(n);
+                for pixel in &mut row[..n] {
+                    *pixel = 0;
+                }
+            }
+        });
+    }
+
+    // Moves the selected planes, leaving the others where they are.
+    fn scroll<F: FnOnce(&mut [u8])>(&mut self, shift: F) {
+        self.dirty = true;
+        let planes = self.planes;
+        let mut moved = self.pixels;
+        shift(&mut moved);
+        for (pixel, moved) in self.pixels.iter_mut().zip(&moved) {
+            *pixel = *pixel & !planes | moved & planes;
+        }
+    }
+
+    fn toggle_physical(&mut self, plane: u8, x: usize, y: usize) -> bool {
+        self.dirty = true;
+        let pixel = &mut self.pixels[offset(x, y)];
+        *pixel ^= plane;
+        *pixel & plane == 0
+    }
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn toggle_low_resolution() {
+        let mut display = Display::new();
+        assert!(!display.toggle(1, 2));
+        assert_eq!(display.get_pixels()[4 * WIDTH + 2], PLANE_1);
+        assert_eq!(display.get_pixels()[5 * WIDTH + 3], PLANE_1);
+        assert!(display.get_pixel(1, 2));
+        assert!(display.toggle(1, 2));
+        assert_eq!(display.get_pixels()[5 * WIDTH + 3], 0);
+    }
+
+    #[test]
+    fn set_resolution_clears() {
+        let mut display = Display::new();
+        display.toggle(0, 0);
+        display.set_resolution(Resolution::High);
+        assert_eq!(display.get_size(), (128, 64));
+        assert!(!display.get_pixel(0, 0));
+    }
+
+    #[test]
+    fn scroll() {
+        let mut display = Display::new();
+        display.set_resolution(Resolution::High);
+        display.toggle(0, 0);
+        display.scroll_right(4);
+        assert!(display.get_pixel(4, 0));
+        display.scroll_down(2);
+        assert!(display.get_pixel(4, 2));
+        display.scroll_left(4);
+        assert!(display.get_pixel(0, 2));
+        display.scroll_left(4);
+        assert!(!display.get_pixels().iter().any(|&pixel| pixel != 0));
+    }
+
+    #[test]
+    fn planes() {
+        let mut display = Display::new();
+        display.toggle_plane(PLANE_1, 0, 0);
+        assert!(!display.toggle_plane(PLANE_2, 0, 0));
+        assert_eq!(display.get_value(0, 0), PLANE_1 | PLANE_2);
+
+        // Only the selected planes are scrolled and cleared.
+        display.set_planes(PLANE_2);
+        display.scroll_right(2);
+        assert_eq!((display.get_value(0, 0), display.get_value(1, 0)), (PLANE_1, PLANE_2));
+        display.clear();
+        assert_eq!((display.get_value(0, 0), display.get_value(1, 0)), (PLANE_1, 0));
+        display.set_resolution(Resolution::High);
+        assert_eq!(display.get_value(0, 0), 0);
+    }
+
+    #[test]
+    fn take_dirty() {
+        let mut display = Display::new();
+        assert!(display.take_dirty());
+        assert!(!display.take_dirty());
+        display.toggle(0, 0);
+        assert!(display.take_dirty());
+        display.scroll_left(4);
+        assert!(display.take_dirty());
+        assert!(!display.take_dirty());
+    }
+}
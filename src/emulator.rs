@@ -0,0 +1,274 @@
+use chip8::coverage::Coverage;
+use chip8::palette::{self, Palette};
+use chip8::profiler::Profiler;
+use chip8::scheduler::{MAX_SPEED, MIN_SPEED};
+use chip8::sha1::sha1_hex;
+use chip8::{cheat, screenshot, Action, Bindings, Cheats, Chip8, CodeWrite, Filter, Instruction, Phosphor, Quirks, RomError, Scheduler, Search, State};
+use std::collections::HashSet;
+
+// Everything needed to run one ROM: the machine, its scheduler and the
+// actions bound to host inputs.
+pub struct Emulator {
+    pub chip8: Chip8,
+    pub scheduler: Scheduler,
+    pub palette: Palette,
+    pub phosphor: Phosphor,
+    // Applied after every frame.
+    pub cheats: Cheats,
+    pub search: Option<Search>,
+    pub profiler: Option<Profiler>,
+    pub coverage: Option<Coverage>,
+    // Writes over code not yet taken by the frontend, each writer and
+    // address only reported once.
+    code_writes: Vec<CodeWrite>,
+    reported: HashSet<(u16, usize)>,
+    bindings: Bindings,
+    rom: Vec<u8>,
+    // Names the file the cheats are saved to.
+    hash: String,
+    name: String,
+    quirks: Quirks,
+    step: bool,
+    next: bool,
+    paused: bool,
+    // Set when a single frame should run while paused.
+    advance: bool,
+    speed: f64,
+    fast_forward: f64,
+    fast_forwarding: bool,
+    // Whether the frontend was told that the program halted.
+    halt_reported: bool,
+}
+
+impl Emulator {
+    pub fn new(rom: Vec<u8>, name: String, quirks: Quirks, scheduler: Scheduler, bindings: Bindings, palette: Palette, filter: Filter) -> Result<Self, RomError> {
+        Ok(Emulator {
+            chip8: Chip8::with_quirks(&rom, quirks)?,
+            speed: scheduler.get_speed(),
+            scheduler,
+            palette,
+            phosphor: Phosphor::new(filter),
+            cheats: Cheats::default(),
+            search: None,
+            profiler: None,
+            coverage: None,
+            code_writes: Vec::new(),
+            reported: HashSet::new(),
+            bindings,
+            hash: sha1_hex(&rom),
+            rom,
+            name,
+            quirks,
+            step: false,
+            next: false,
+            paused: false,
+            advance: false,
+            fast_forward: 1.0,
+            fast_forwarding: false,
+            halt_reported: false,
+        })
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        &self.bindings
+    }
+
+    // The file name of the ROM without its extension.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // The speed multiplier while the fast forward action is held.
+    pub fn set_fast_forward(&mut self, multiplier: f64) {
+        self.fast_forward = multiplier;
+    }
+
+    // Short text describing anything but running at normal speed, for the
+    // frontends to show.
+    pub fn indicator(&self) -> Option<String> {
+        let speed = self.scheduler.get_speed();
+        if let State::Halted { .. } = self.chip8.get_state() {
+            Some("HALT".to_string())
+        } else if self.step {
+            Some("STEP".to_string())
+        } else if self.paused {
+            Some("PAUSE".to_string())
+        } else if self.fast_forwarding {
+            Some(format!(">> X{}", speed))
+        } else if speed != 1.0 {
+            Some(format!("X{}", speed))
+        } else {
+            None
+        }
+    }
+
+    // Returns a message for the frontend to show, if the action produced one.
+    pub fn input(&mut self, name: &str, pressed: bool) -> Option<String> {
+        match (self.bindings.get(name), pressed) {
+            (Some(Action::Keypad(index)), _) => self.chip8.push_key_event(index, pressed),
+            (Some(Action::Next), true) if self.step => self.next = true,
+            (Some(Action::Step), false) => self.step = !self.step,
+            (Some(Action::Reset), false) => match Chip8::with_quirks(&self.rom, self.quirks) {
+                Ok(mut chip8) => {
+                    chip8.set_cycle_counting(self.profiler.is_some());
+                    self.chip8 = chip8;
+                    self.halt_reported = false;
+                }
+                Err(error) => return Some(format!("could not reset: {}", error)),
+            },
+            (Some(Action::Palette), false) => self.next_palette(),
+            (Some(Action::Screenshot), false) => return Some(self.screenshot()),
+            (Some(Action::Pause), false) => self.paused = !self.paused,
+            (Some(Action::Frame), true) => {
+                self.paused = true;
+                self.advance = true;
+            }
+            (Some(Action::FastForward), _) => {
+                self.fast_forwarding = pressed;
+                self.update_speed();
+            }
+            (Some(Action::Slower), true) => {
+                self.speed = (self.speed / 2.0).max(MIN_SPEED);
+                self.update_speed();
+            }
+            (Some(Action::Faster), true) => {
+                self.speed = (self.speed * 2.0).min(MAX_SPEED);
+                self.update_speed();
+            }
+            _ => (),
+        }
+        None
+    }
+
+    fn update_speed(&mut self) {
+        let multiplier = if self.fast_forwarding { self.fast_forward } else { 1.0 };
+        self.scheduler.set_speed(self.speed * multiplier);
+    }
+
+    // Saves the filtered display to the screenshots directory.
+    fn screenshot(&mut self) -> String {
+        let dir = match screenshot::directory() {
+            Some(dir) => dir,
+            None => return "no directory for screenshots, set HOME or XDG_DATA_HOME".to_string(),
+        };
+        match screenshot::save(&self.phosphor, &self.palette, &dir, &self.name) {
+            Ok(path) => format!("saved {}", path.display()),
+            Err(error) => format!("could not save screenshot: {}", error),
+        }
+    }
+
+    pub fn load_cheats(&mut self) -> Result<(), String> {
+        if let Some(dir) = cheat::directory() {
+            self.cheats = Cheats::load(&dir, &self.hash)?;
+        }
+        Ok(())
+    }
+
+    // Saves the cheats for this ROM, returning a message for the frontend.
+    pub fn save_cheats(&self) -> String {
+        let dir = match cheat::directory() {
+            Some(dir) => dir,
+            None => return "no directory for cheats, set HOME or XDG_DATA_HOME".to_string(),
+        };
+        match self.cheats.save(&dir, &self.hash) {
+            Ok(path) => format!("saved {}", path.display()),
+            Err(error) => format!("could not save cheats: {}", error),
+        }
+    }
+
+    // Writes the ROM made over its own code since the last call, for the
+    // frontends to report.
+    pub fn take_code_writes(&mut self) -> Vec<CodeWrite> {
+        std::mem::take(&mut self.code_writes)
+    }
+
+    // Why the program halted, once after it did, for the frontends to
+    // report. It stays halted until reset.
+    pub fn take_halt(&mut self) -> Option<String> {
+        match self.chip8.get_state() {
+            State::Halted { pc, halt } if !self.halt_reported => {
+                self.halt_reported = true;
+                let instruction = Instruction::decode(self.chip8.get_memory(), pc as usize).map_or(String::new(), |instruction| instruction.to_string());
+                Some(format!("{} at {:03X}: {}", halt, pc, instruction))
+            }
+            _ => None,
+        }
+    }
+
+    // Switches to the next named palette, or the first one when a custom
+    // palette is in use.
+    fn next_palette(&mut self) {
+        let current = palette::NAMES.iter().position(|name| Palette::named(name) == Some(self.palette));
+        let next = current.map_or(0, |index| (index + 1) % palette::NAMES.len());
+        self.palette = Palette::named(palette::NAMES[next]).unwrap();
+    }
+
+    // Runs `dt` seconds of emulation, or a single instruction when stepping,
+    // which is returned for the frontend to show. While paused only frames
+    // asked for with the frame action run.
+    pub fn update(&mut self, dt: f64) -> Option<Instruction> {
+        if self.paused && !self.step {
+            if self.advance {
+                self.advance = false;
+                self.run_frame();
+            }
+            return None;
+        }
+
+        if !self.step {
+            for _ in 0..self.scheduler.due_frames(dt) {
+                self.run_frame();
+            }
+            return None;
+        }
+
+        if !self.next {
+            return None;
+        }
+
+        self.next = false;
+        self.chip8.process_key_events();
+        let pc = self.chip8.get_pc();
+        let instruction = self.chip8.tick();
+        record(&mut self.profiler, &mut self.coverage, &self.chip8, pc, &instruction);
+        self.after_update();
+        Some(instruction)
+    }
+
+    // Runs a frame, recording its instructions when profiling or tracking
+    // coverage.
+    fn run_frame(&mut self) {
+        if self.profiler.is_none() && self.coverage.is_none() {
+            self.scheduler.run_frame(&mut self.chip8);
+        } else {
+            let (profiler, coverage) = (&mut self.profiler, &mut self.coverage);
+            self.scheduler.run_frame_with(&mut self.chip8, |chip8, pc, instruction| {
+                record(profiler, coverage, chip8, pc, instruction);
+                true
+            });
+        }
+        self.after_update();
+    }
+
+    // Applies the cheats, collects writes over code and passes any change of
+    // the display on to the filter.
+    fn after_update(&mut self) {
+        self.cheats.apply(&mut self.chip8);
+        for write in self.chip8.take_code_writes() {
+            if self.reported.insert((write.pc, write.address)) {
+                self.code_writes.push(write);
+            }
+        }
+        let dirty = self.chip8.take_display_dirty();
+        self.phosphor.update(self.chip8.get_display(), dirty);
+    }
+}
+
+fn record(profiler: &mut Option<Profiler>, coverage: &mut Option<Coverage>, chip8: &Chip8, pc: u16, instruction: &Instruction) {
+    if let Some(ref mut profiler) = *profiler {
+        profiler.record(pc, instruction, chip8.get_cycles());
+    }
+    if let Some(ref mut coverage) = *coverage {
+        coverage.record(chip8, pc, instruction);
+    }
+}
@@ -0,0 +1,219 @@
+use display::{Display, Resolution, HEIGHT, PLANES, PLANE_1, PLANE_2, WIDTH};
+use palette::{Colour, Palette};
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+// Softens the flicker of sprites that are erased and redrawn every frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    None,
+    // Averages each pixel over the last n frames.
+    Blend(usize),
+    // Lets pixels that were turned off fade out, keeping this fraction of
+    // their brightness every frame.
+    Fade(f32),
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    // `none`, `blend:<frames>` or `fade:<0.0-1.0>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("none"), None) => Ok(Filter::None),
+            (Some("blend"), Some(frames)) => match frames.parse() {
+                Ok(frames) if frames > 0 => Ok(Filter::Blend(frames)),
+                _ => Err(format!("invalid number of frames '{}'", frames)),
+            },
+            (Some("fade"), Some(strength)) => match strength.parse() {
+                Ok(strength) if (0.0..1.0).contains(&strength) => Ok(Filter::Fade(strength)),
+                _ => Err(format!("invalid fade strength '{}', expected 0.0 up to 1.0", strength)),
+            },
+            _ => Err(format!("unknown filter '{}', expected none, blend:<frames> or fade:<strength>", s)),
+        }
+    }
+}
+
+// Keeps the filtered brightness of every pixel in each plane, updated once
+// per frame. Both the frontends and screenshots take their pixels from here.
+pub struct Phosphor {
+    filter: Filter,
+    resolution: Resolution,
+    history: VecDeque<Vec<u8>>,
+    intensities: Vec<[f32; 2]>,
+    // Whether every intensity has settled on its pixel, so that frames in
+    // which the display did not change leave them alone.
+    steady: bool,
+    changed: bool,
+}
+
+impl Phosphor {
+    pub fn new(filter: Filter) -> Self {
+        Phosphor {
+            filter,
+            resolution: Resolution::Low,
+            history: VecDeque::new(),
+            intensities: vec![[0.0; 2]; WIDTH * HEIGHT],
+            steady: false,
+            changed: true,
+        }
+    }
+
+    // Runs the filter for one frame, `dirty` telling whether the display
+    // changed since the last one.
+    pub fn update(&mut self, display: &Display, dirty: bool) {
+        if !dirty && self.steady && display.get_resolution() == self.resolution {
+            return;
+        }
+
+        let pixels = display.get_pixels();
+
+        if display.get_resolution() != self.resolution {
+            self.resolution = display.get_resolution();
+            self.history.clear();
+            self.intensities.iter_mut().for_each(|intensity| *intensity = [0.0; 2]);
+        }
+
+        match self.filter {
+            Filter::None => {
+                for (intensity, &pixel) in self.intensities.iter_mut().zip(pixels) {
+                    *intensity = levels(pixel);
+                }
+            }
+            Filter::Blend(frames) => {
+                self.history.push_back(pixels.to_vec());
+                while self.history.len() > frames {
+                    self.history.pop_front();
+                }
+
+                let count = self.history.len() as f32;
+                for (index, intensity) in self.intensities.iter_mut().enumerate() {
+                    for (level, &plane) in intensity.iter_mut().zip(&PLANES) {
+                        *level = self.history.iter().filter(|frame| frame[index] & plane != 0).count() as f32 / count;
+                    }
+                }
+            }
+            Filter::Fade(strength) => {
+                for (intensity, &pixel) in self.intensities.iter_mut().zip(pixels) {
+                    for (level, on) in intensity.iter_mut().zip(levels(pixel).iter().map(|&on| on == 1.0)) {
+                        *level = if on { 1.0 } else { *level * strength };
+                        // Below one step of an 8 bit colour channel the pixel
+                        // is as good as off.
+                        if *level < 1.0 / 256.0 {
+                            *level = 0.0;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.steady = self.intensities.iter().zip(pixels).all(|(&intensity, &pixel)| intensity == levels(pixel));
+        self.changed = true;
+    }
+
+    // Returns whether any intensity changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.changed;
+        self.changed = false;
+        changed
+    }
+
+    pub fn get_size(&self) -> (usize, usize) {
+        self.resolution.size()
+    }
+
+    // The brightness of a logical pixel in the brighter of its planes.
+    pub fn get_intensity(&self, x: usize, y: usize) -> f32 {
+        let [first, second] = self.get_levels(x, y);
+        first.max(second)
+    }
+
+    fn get_levels(&self, x: usize, y: usize) -> [f32; 2] {
+        let scale = self.resolution.scale();
+        self.intensities[y * scale * WIDTH + x * scale]
+    }
+
+    // The colour of a logical pixel, mixing the four colours of the palette
+    // by how lit it is in each plane.
+    pub fn get_colour(&self, palette: &Palette, x: usize, y: usize) -> Colour {
+        let [first, second] = self.get_levels(x, y);
+        let weights = [(1.0 - first) * (1.0 - second), first * (1.0 - second), (1.0 - first) * second, first * second];
+        let mut colour = [0.0; 4];
+        for (weight, value) in weights.iter().zip(&palette.colours) {
+            for (channel, &component) in colour.iter_mut().zip(value) {
+                *channel += weight * component;
+            }
+        }
+        colour
+    }
+}
+
+// Full brightness in the planes a pixel is set in.
+fn levels(pixel: u8) -> [f32; 2] {
+    [(pixel & PLANE_1 != 0) as u8 as f32, (pixel & PLANE_2 != 0) as u8 as f32]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blend() {
+        let mut display = Display::new();
+        let mut phosphor = Phosphor::new(Filter::Blend(2));
+        display.toggle(1, 1);
+        phosphor.update(&display, true);
+        assert_eq!(phosphor.get_intensity(1, 1), 1.0);
+        display.toggle(1, 1);
+        phosphor.update(&display, true);
+        assert_eq!(phosphor.get_intensity(1, 1), 0.5);
+        phosphor.update(&display, true);
+        assert_eq!(phosphor.get_intensity(1, 1), 0.0);
+    }
+
+    #[test]
+    fn fade() {
+        let mut display = Display::new();
+        let mut phosphor = Phosphor::new(Filter::Fade(0.5));
+        display.toggle(1, 1);
+        phosphor.update(&display, true);
+        display.toggle(1, 1);
+        phosphor.update(&display, true);
+        assert_eq!(phosphor.get_intensity(1, 1), 0.5);
+        phosphor.update(&display, true);
+        assert_eq!(phosphor.get_intensity(1, 1), 0.25);
+    }
+
+    #[test]
+    fn take_changed() {
+        let mut display = Display::new();
+        let mut phosphor = Phosphor::new(Filter::Fade(0.5));
+        display.toggle(1, 1);
+        phosphor.update(&display, true);
+        assert!(phosphor.take_changed());
+        display.toggle(1, 1);
+        phosphor.update(&display, true);
+        assert!(phosphor.take_changed());
+
+        // Still fading out although the display did not change.
+        phosphor.update(&display, false);
+        assert!(phosphor.take_changed());
+
+        for _ in 0..8 {
+            phosphor.update(&display, false);
+        }
+        phosphor.take_changed();
+        phosphor.update(&display, false);
+        assert!(!phosphor.take_changed());
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("blend:3".parse(), Ok(Filter::Blend(3)));
+        assert_eq!("fade:0.6".parse(), Ok(Filter::Fade(0.6)));
+        assert_eq!("none".parse(), Ok(Filter::None));
+        assert!("fade:1.5".parse::<Filter>().is_err());
+        assert!("blend:0".parse::<Filter>().is_err());
+    }
+}
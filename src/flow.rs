@@ -0,0 +1,294 @@
+use chip8::ROM_OFFSET;
+use instruction::Instruction;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edge {
+    // Running on into the next block, or coming back from a call.
+    Next,
+    Jump,
+    Call,
+    // The instruction a skip jumps to when its condition holds.
+    Skip,
+}
+
+// Instructions from `start` up to but not including `end`, only entered at
+// the start and only left at the end. `end` is 0 for a block that runs to the
+// end of memory.
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    pub start: u16,
+    pub end: u16,
+    pub edges: Vec<(Edge, u16)>,
+    // Ends in `JP V0, nnn`, whose target isn't known without running it.
+    pub computed_jump: bool,
+    // Addresses of instructions that write over code, with I known from an
+    // `LD I, nnn` earlier in the block.
+    pub code_writes: Vec<u16>,
+}
+
+// The control flow of a ROM found by following every path from 0x200
+// without running it: the basic blocks, and which subroutines call which,
+// 0x200 counting as the subroutine everything starts from.
+pub struct FlowGraph {
+    pub blocks: BTreeMap<u16, Block>,
+    pub calls: BTreeSet<(u16, u16)>,
+    memory: Vec<u8>,
+}
+
+fn decode(memory: &[u8], address: u16) -> Option<Instruction> {
+    Instruction::decode(memory, address as usize)
+}
+
+// The address `offset` bytes on from `address`, wrapping around at the end
+// of memory as the PC does.
+fn offset(memory: &[u8], address: u16, offset: u16) -> u16 {
+    address.wrapping_add(offset) & (memory.len() - 1) as u16
+}
+
+// Where control can go after the instruction at `address`, and whether the
+// instruction ends a block even when nothing jumps to the next one.
+fn successors(memory: &[u8], instruction: &Instruction, address: u16) -> (Vec<(Edge, u16)>, bool) {
+    use self::Instruction::*;
+
+    let next = offset(memory, address, instruction.size());
+    // Skips jump over `LD I, long` as a whole.
+    let skipped = offset(memory, next, decode(memory, next).map_or(2, |instruction| instruction.size()));
+    match *instruction {
+        JPnnn { nnn } => (vec![(Edge::Jump, nnn)], true),
+        CALL { nnn } => (vec![(Edge::Call, nnn), (Edge::Next, next)], true),
+        RET | EXIT | JPnnnv { .. } | INVALID { .. } => (Vec::new(), true),
+        SExkk { .. } | SNExkk { .. } | SExy { .. } | SNExy { .. } | SKP { .. } | SKNP { .. } => (vec![(Edge::Next, next), (Edge::Skip, skipped)], true),
+        // Running off the end of memory wraps around to 0, which starts a
+        // block of its own.
+        _ => (vec![(Edge::Next, next)], next == 0),
+    }
+}
+
+// Whether writing `written` bytes from `i` changes any of the instructions
+// in `code`. Writing one byte of an instruction is enough, and `LD I, long`
+// starting up to three bytes before `i` covers it too.
+fn writes_code(memory: &[u8], code: &BTreeSet<u16>, i: u16, written: u16) -> bool {
+    let covers = |back: u16| {
+        let start = offset(memory, i, back.wrapping_neg());
+        code.contains(&start) && decode(memory, start).is_some_and(|instruction| instruction.size() > back)
+    };
+    (1..4).any(covers) || (0..written).any(|target| code.contains(&offset(memory, i, target)))
+}
+
+impl FlowGraph {
+    pub fn new(memory: &[u8]) -> Self {
+        let entry = ROM_OFFSET as u16;
+
+        // Find every reachable instruction, and the ones blocks start at.
+        let mut code = BTreeSet::new();
+        let mut leaders: BTreeSet<u16> = [entry].iter().cloned().collect();
+        let mut pending = vec![entry];
+        while let Some(address) = pending.pop() {
+            let instruction = match decode(memory, address) {
+                Some(instruction) if code.insert(address) => instruction,
+                _ => continue,
+            };
+            let (edges, ends_block) = successors(memory, &instruction, address);
+            for &(_, target) in &edges {
+                if ends_block {
+                    leaders.insert(target);
+                }
+                pending.push(target);
+            }
+        }
+
+        let mut blocks = BTreeMap::new();
+        for &start in leaders.iter().filter(|leader| code.contains(leader)) {
+            let mut address = start;
+            let mut i = None;
+            let mut code_writes = Vec::new();
+            loop {
+                let instruction = decode(memory, address).unwrap();
+                match instruction {
+                    Instruction::LDnnn { nnn } | Instruction::LDnnnn { nnnn: nnn } => i = Some(nnn),
+                    Instruction::ADDix { .. } | Instruction::LDfx { .. } | Instruction::LDhfx { .. } => i = None,
+                    _ => (),
+                }
+                let written = match instruction {
+                    Instruction::LDix { x } => x as u16 + 1,
+                    Instruction::LDbx { .. } => 3,
+                    _ => 0,
+                };
+                match i {
+                    Some(i) if written > 0 && writes_code(memory, &code, i, written) => code_writes.push(address),
+                    _ => (),
+                }
+
+                let (edges, ends_block) = successors(memory, &instruction, address);
+                let next = offset(memory, address, instruction.size());
+                if ends_block || leaders.contains(&next) || !code.contains(&next) {
+                    let computed_jump = matches!(instruction, Instruction::JPnnnv { .. });
+                    let edges = edges.into_iter().filter(|(_, target)| code.contains(target)).collect();
+                    blocks.insert(start, Block { start, end: next, edges, computed_jump, code_writes });
+                    break;
+                }
+                address = next;
+            }
+        }
+
+        // Walk each subroutine without following calls to find the ones it
+        // makes.
+        let mut calls = BTreeSet::new();
+        let mut subroutines = vec![entry];
+        let mut seen_subroutines: BTreeSet<u16> = subroutines.iter().cloned().collect();
+        while let Some(subroutine) = subroutines.pop() {
+            let mut seen = BTreeSet::new();
+            let mut pending = vec![subroutine];
+            while let Some(start) = pending.pop() {
+                let block = match blocks.get(&start) {
+                    Some(block) if seen.insert(start) => block,
+                    _ => continue,
+                };
+                for &(edge, target) in &block.edges {
+                    if edge == Edge::Call {
+                        calls.insert((subroutine, target));
+                        if seen_subroutines.insert(target) {
+                            subroutines.push(target);
+                        }
+                    } else {
+                        pending.push(target);
+                    }
+                }
+            }
+        }
+
+        FlowGraph { blocks, calls, memory: memory.to_vec() }
+    }
+
+    // The control flow graph in Graphviz DOT, a node of disassembly for each
+    // block. Computed jumps and writes over code are drawn in red.
+    pub fn to_dot(&self) -> String {
+        let mut dot = "digraph flow {\n    node [shape=box, fontname=monospace];\n".to_string();
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            let mut address = block.start;
+            loop {
+                let instruction = decode(&self.memory, address).unwrap();
+                let note = if block.code_writes.contains(&address) { "  ; writes code" } else { "" };
+                write!(label, "{:03X}  {}{}\\l", address, instruction, note).unwrap();
+                address = offset(&self.memory, address, instruction.size());
+                if address == block.end {
+                    break;
+                }
+            }
+            if block.computed_jump {
+                label += "; computed jump\\l";
+            }
+            let colour = if block.computed_jump || !block.code_writes.is_empty() { ", color=red" } else { "" };
+            writeln!(dot, "    \"{:03X}\" [label=\"{}\"{}];", block.start, label, colour).unwrap();
+
+            for &(edge, target) in &block.edges {
+                let style = match edge {
+                    Edge::Next => "",
+                    Edge::Jump => " [label=jump]",
+                    Edge::Call => " [label=call, style=dashed]",
+                    Edge::Skip => " [label=skip]",
+                };
+                writeln!(dot, "    \"{:03X}\" -> \"{:03X}\"{};", block.start, target, style).unwrap();
+            }
+        }
+        dot + "}\n"
+    }
+
+    // Which subroutines call which, in Graphviz DOT.
+    pub fn calls_to_dot(&self) -> String {
+        let mut dot = "digraph calls {\n    node [shape=box, fontname=monospace];\n".to_string();
+        writeln!(dot, "    \"{:03X}\";", ROM_OFFSET).unwrap();
+        for &(caller, callee) in &self.calls {
+            writeln!(dot, "    \"{:03X}\" -> \"{:03X}\";", caller, callee).unwrap();
+        }
+        dot + "}\n"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chip8::Chip8;
+    use platform::Platform;
+
+    // CALL 0x20E; SE V0, 0; JP 0x200; LD I, 0x200; LD [I], V0;
+    // JP V0, 0x300; a word of data; LD V0, 1; RET.
+    const ROM: [u8; 18] = [0x22, 0x0E, 0x30, 0x00, 0x12, 0x00, 0xA2, 0x00, 0xF0, 0x55, 0xB3, 0x00, 0x00, 0x00, 0x60, 0x01, 0x00, 0xEE];
+
+    fn graph() -> FlowGraph {
+        FlowGraph::new(Chip8::new(&ROM).unwrap().get_memory())
+    }
+
+    #[test]
+    fn blocks() {
+        let graph = graph();
+        let starts: Vec<u16> = graph.blocks.keys().cloned().collect();
+        assert_eq!(starts, [0x200, 0x202, 0x204, 0x206, 0x20E]);
+
+        assert_eq!(graph.blocks[&0x200].edges, [(Edge::Call, 0x20E), (Edge::Next, 0x202)]);
+        assert_eq!(graph.blocks[&0x202].edges, [(Edge::Next, 0x204), (Edge::Skip, 0x206)]);
+        assert_eq!(graph.blocks[&0x204].edges, [(Edge::Jump, 0x200)]);
+
+        let block = &graph.blocks[&0x206];
+        assert_eq!(block.end, 0x20C);
+        assert!(block.computed_jump);
+        assert_eq!(block.code_writes, [0x208]);
+        assert!(block.edges.is_empty());
+
+        assert_eq!(graph.calls.iter().cloned().collect::<Vec<_>>(), [(0x200, 0x20E)]);
+    }
+
+    #[test]
+    fn dot() {
+        let graph = graph();
+        let dot = graph.to_dot();
+        assert!(dot.contains("    \"206\" [label=\"206  LD I, 0x200\\l208  LD [I], V0  ; writes code\\l20A  JP V0, 0x300\\l; computed jump\\l\", color=red];\n"));
+        assert!(dot.contains("    \"200\" -> \"20E\" [label=call, style=dashed];\n"));
+        assert!(dot.contains("    \"202\" -> \"206\" [label=skip];\n"));
+        assert_eq!(graph.calls_to_dot(), "digraph calls {\n    node [shape=box, fontname=monospace];\n    \"200\";\n    \"200\" -> \"20E\";\n}\n");
+    }
+
+    #[test]
+    fn top_of_memory() {
+        // LD V0, 1 all the way up to the end of memory, wrapping around to
+        // JP 0x200 at 0.
+        let rom: Vec<u8> = [0x60, 0x01].iter().cloned().cycle().take(0x10000 - ROM_OFFSET).collect();
+        let mut chip8 = Chip8::new(&rom).unwrap();
+        chip8.set_memory(0, 0x12);
+        chip8.set_memory(1, 0x00);
+
+        let graph = FlowGraph::new(chip8.get_memory());
+        let block = &graph.blocks[&0x200];
+        assert_eq!(block.end, 0);
+        assert_eq!(block.edges, [(Edge::Next, 0)]);
+        assert_eq!(graph.blocks[&0].edges, [(Edge::Jump, 0x200)]);
+        assert!(graph.to_dot().contains("FFFE  LD V0, 0x01\\l\"];"));
+
+        // 4 KiB of memory wrap around at 0x1000.
+        let mut chip8 = Chip8::with_quirks(&rom[..0x1000 - ROM_OFFSET], Platform::Chip8.quirks()).unwrap();
+        chip8.set_memory(0, 0x12);
+        chip8.set_memory(1, 0x00);
+
+        let graph = FlowGraph::new(chip8.get_memory());
+        assert_eq!(graph.blocks[&0x200].end, 0);
+        assert_eq!(graph.blocks[&0x200].edges, [(Edge::Next, 0)]);
+        assert!(graph.to_dot().contains("FFE  LD V0, 0x01\\l\"];"));
+    }
+
+    #[test]
+    fn writes_long_i() {
+        // LD I, 0x207; LD [I], V0; LD I, long 0x0300; JP 0x200, writing the
+        // last byte of the `LD I, long`.
+        let rom = [0xA2, 0x07, 0xF0, 0x55, 0xF0, 0x00, 0x03, 0x00, 0x12, 0x00];
+        let graph = FlowGraph::new(Chip8::new(&rom).unwrap().get_memory());
+        assert_eq!(graph.blocks[&0x200].code_writes, [0x202]);
+
+        // Just past the JP, which is only two bytes long.
+        let rom = [0xA2, 0x0A, 0xF0, 0x55, 0xF0, 0x00, 0x03, 0x00, 0x12, 0x00];
+        let graph = FlowGraph::new(Chip8::new(&rom).unwrap().get_memory());
+        assert!(graph.blocks[&0x200].code_writes.is_empty());
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver};
+
+// Stick positions beyond half way count as a direction being pressed.
+const AXIS_THRESHOLD: i16 = i16::MAX / 2;
+// How often to look for a controller while none is connected.
+#[cfg(target_os = "linux")]
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+#[cfg(target_os = "linux")]
+const DEVICE: &str = "/dev/input/js0";
+
+// Event types of the Linux joystick API. The state sent on connecting is
+// flagged with 0x80 as well.
+const BUTTON: u8 = 0x01;
+const AXIS: u8 = 0x02;
+
+const UP: u8 = 1 << 0;
+const DOWN: u8 = 1 << 1;
+const LEFT: u8 = 1 << 2;
+const RIGHT: u8 = 1 << 3;
+
+const DIRECTIONS: [(u8, &str); 4] = [(UP, "pad_up"), (DOWN, "pad_down"), (LEFT, "pad_left"), (RIGHT, "pad_right")];
+
+// Turns controller events into named inputs for `Bindings`: `pad_button<N>`
+// for buttons and `pad_up`, `pad_down`, `pad_left` and `pad_right` for both
+// the first stick (axes 0 and 1) and the d-pad (axes 6 and 7 on common
+// controllers). Axes only report positions, so the directions held by each
+// are tracked to produce presses and releases.
+//
+// Events are read from the Linux joystick device on a thread of their own,
+// which waits for a controller to be connected and again after it's
+// unplugged. Without one, or on other systems, there are simply no events.
+pub struct Gamepad {
+    events: Receiver<[u8; 8]>,
+    directions: HashMap<u8, u8>,
+}
+
+impl Gamepad {
+    pub fn open() -> Self {
+        let (sender, events) = mpsc::channel();
+        #[cfg(target_os = "linux")]
+        std::thread::spawn(move || read_device(sender));
+        #[cfg(not(target_os = "linux"))]
+        drop(sender);
+        Gamepad { events, directions: HashMap::new() }
+    }
+
+    // The inputs since the last call.
+    pub fn poll(&mut self) -> Vec<(String, bool)> {
+        let events: Vec<[u8; 8]> = self.events.try_iter().collect();
+        events.into_iter().flat_map(|event| self.event(event)).collect()
+    }
+
+    // Takes a `js_event`: the time, the value, the type and the number of the
+    // button or axis.
+    fn event(&mut self, event: [u8; 8]) -> Vec<(String, bool)> {
+        let value = i16::from_ne_bytes([event[4], event[5]]);
+        let number = event[7];
+        match event[6] {
+            BUTTON => vec![(format!("pad_button{}", number), value != 0)],
+            AXIS => self.axis(number, value),
+            // The state sent on connecting is left out, so that nothing
+            // seems to be released.
+            _ => Vec::new(),
+        }
+    }
+
+    fn axis(&mut self, number: u8, value: i16) -> Vec<(String, bool)> {
+        let (negative, positive) = match number {
+            0 | 6 => (LEFT, RIGHT),
+            1 | 7 => (UP, DOWN),
+            _ => return Vec::new(),
+        };
+        let directions = if value <= -AXIS_THRESHOLD {
+            negative
+        } else if value >= AXIS_THRESHOLD {
+            positive
+        } else {
+            0
+        };
+
+        let previous = self.directions.insert(number, directions).unwrap_or(0);
+        let changed = previous ^ directions;
+        DIRECTIONS
+            .iter()
+            .filter(|&&(direction, _)| changed & direction != 0)
+            .map(|&(direction, name)| (name.to_string(), directions & direction != 0))
+            .collect()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_device(sender: mpsc::Sender<[u8; 8]>) {
+    use std::fs::File;
+    use std::io::Read;
+
+    loop {
+        if let Ok(mut device) = File::open(DEVICE) {
+            let mut event = [0; 8];
+            while device.read_exact(&mut event).is_ok() {
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+        }
+        std::thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chip8::{Action, Bindings, Config};
+
+    // Without the device thread.
+    fn gamepad() -> Gamepad {
+        Gamepad { events: mpsc::channel().1, directions: HashMap::new() }
+    }
+
+    fn event(kind: u8, number: u8, value: i16) -> [u8; 8] {
+        let value = value.to_ne_bytes();
+        [0, 0, 0, 0, value[0], value[1], kind, number]
+    }
+
+    fn inputs(list: &[(&str, bool)]) -> Vec<(String, bool)> {
+        list.iter().map(|&(name, pressed)| (name.to_string(), pressed)).collect()
+    }
+
+    #[test]
+    fn buttons() {
+        let mut gamepad = gamepad();
+        assert_eq!(gamepad.event(event(BUTTON, 2, 1)), inputs(&[("pad_button2", true)]));
+        assert_eq!(gamepad.event(event(BUTTON, 2, 0)), inputs(&[("pad_button2", false)]));
+        assert_eq!(gamepad.event(event(BUTTON | 0x80, 3, 1)), inputs(&[]));
+    }
+
+    #[test]
+    fn axes() {
+        let mut gamepad = gamepad();
+        assert_eq!(gamepad.event(event(AXIS, 1, -4000)), inputs(&[]));
+        assert_eq!(gamepad.event(event(AXIS, 1, -30000)), inputs(&[("pad_up", true)]));
+        assert_eq!(gamepad.event(event(AXIS, 1, 32767)), inputs(&[("pad_up", false), ("pad_down", true)]));
+        assert_eq!(gamepad.event(event(AXIS, 6, 32767)), inputs(&[("pad_right", true)]));
+        assert_eq!(gamepad.event(event(AXIS, 6, 0)), inputs(&[("pad_right", false)]));
+        assert_eq!(gamepad.event(event(AXIS, 3, 32767)), inputs(&[]));
+    }
+
+    // Controller inputs are bound like keys, per ROM too.
+    #[test]
+    fn bindings() {
+        let config = Config::parse("[keys TETRIS]\npad_button2 = a\n").unwrap();
+        let bindings = Bindings::load(&config, Some("TETRIS"), &[]).unwrap();
+        let mut gamepad = gamepad();
+        let events = [event(AXIS, 0, -32767), event(BUTTON, 2, 1)];
+        let actions: Vec<Option<Action>> = events.iter().flat_map(|&event| gamepad.event(event)).map(|(name, _)| bindings.get(&name)).collect();
+        assert_eq!(actions, [Some(Action::Keypad(0x4)), Some(Action::Keypad(0xA))]);
+    }
+}
@@ -1,4 +1,9 @@
-#[derive(Debug)]
+use std::fmt;
+
+// The first word of `LD I, long`.
+pub const LONG_OPCODE: u16 = 0xF000;
+
+#[derive(Clone, Copy, Debug)]
 pub enum Instruction {
     ADDix { x: usize },
     ADDxkk { x: usize, kk: u8 },
@@ -18,6 +23,8 @@ pub enum Instruction {
     LDhfx { x: usize },
     LDix { x: usize },
     LDnnn { nnn: u16 },
+    // XO-CHIP's F000 NNNN, loading I from the word after it.
+    LDnnnn { nnnn: u16 },
     LDrx { x: usize },
     LDsx { x: usize },
     LDtx { x: usize },
@@ -29,6 +36,8 @@ pub enum Instruction {
     LDxy { x: usize, y: usize },
     LOW,
     OR { x: usize, y: usize },
+    // XO-CHIP's FN01, selecting the bit planes to draw to.
+    PLANE { n: u8 },
     RET,
     RND { x: usize, kk: u8 },
     SCDn { n: u8 },
@@ -48,6 +57,31 @@ pub enum Instruction {
 }
 
 impl Instruction {
+    // On the COSMAC VIP sprite drawing waits for the display interrupt first,
+    // so a DRW always ends the current frame.
+    pub fn waits_for_vblank(&self) -> bool {
+        matches!(*self, Instruction::DRW { .. } | Instruction::DRWH { .. })
+    }
+
+    // The instruction at `address`, unless it runs past the end of memory.
+    pub fn decode(memory: &[u8], address: usize) -> Option<Self> {
+        let word = |address: usize| memory.get(address..address + 2).map(|bytes| u16::from(bytes[0]) << 8 | u16::from(bytes[1]));
+        match word(address)? {
+            LONG_OPCODE => word(address + 2).map(|nnnn| Instruction::LDnnnn { nnnn }),
+            opcode => Some(Self::from_opcode(opcode)),
+        }
+    }
+
+    // In bytes, which is 2 for everything but `LD I, long`.
+    pub fn size(&self) -> u16 {
+        match *self {
+            Instruction::LDnnnn { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    // Opcodes on their own, F000 being shown as an invalid one as its
+    // address is missing.
     pub fn from_opcode(opcode: u16) -> Self {
         use self::Instruction::*;
 
@@ -95,6 +129,7 @@ impl Instruction {
             (0xD, _, _, _) => DRW { x, y, n },
             (0xE, _, 0x9, 0xE) => SKP { x },
             (0xE, _, 0xA, 0x1) => SKNP { x },
+            (0xF, _, 0x0, 0x1) if x <= 3 => PLANE { n: x as u8 },
             (0xF, _, 0x0, 0x7) => LDxt { x },
             (0xF, _, 0x0, 0xA) => LDx { x },
             (0xF, _, 0x1, 0x5) => LDtx { x },
@@ -111,3 +146,89 @@ impl Instruction {
         }
     }
 }
+
+// Mnemonics in the style of Cowgod's reference, with bytes and addresses in
+// hex. Opcodes that aren't instructions are shown as a data word.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Instruction::*;
+
+        match *self {
+            ADDix { x } => write!(f, "ADD I, V{:X}", x),
+            ADDxkk { x, kk } => write!(f, "ADD V{:X}, 0x{:02X}", x, kk),
+            ADDxy { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            AND { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            CALL { nnn } => write!(f, "CALL 0x{:03X}", nnn),
+            CLS => write!(f, "CLS"),
+            DRW { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            DRWH { x, y } => write!(f, "DRW V{:X}, V{:X}, 0", x, y),
+            EXIT => write!(f, "EXIT"),
+            INVALID { opcode } => write!(f, "DW 0x{:04X}", opcode),
+            HIGH => write!(f, "HIGH"),
+            JPnnn { nnn } => write!(f, "JP 0x{:03X}", nnn),
+            JPnnnv { nnn } => write!(f, "JP V0, 0x{:03X}", nnn),
+            LDbx { x } => write!(f, "LD B, V{:X}", x),
+            LDfx { x } => write!(f, "LD F, V{:X}", x),
+            LDhfx { x } => write!(f, "LD HF, V{:X}", x),
+            LDix { x } => write!(f, "LD [I], V{:X}", x),
+            LDnnn { nnn } => write!(f, "LD I, 0x{:03X}", nnn),
+            LDnnnn { nnnn } => write!(f, "LD I, long 0x{:04X}", nnnn),
+            LDrx { x } => write!(f, "LD R, V{:X}", x),
+            LDsx { x } => write!(f, "LD ST, V{:X}", x),
+            LDtx { x } => write!(f, "LD DT, V{:X}", x),
+            LDx { x } => write!(f, "LD V{:X}, K", x),
+            LDxi { x } => write!(f, "LD V{:X}, [I]", x),
+            LDxkk { x, kk } => write!(f, "LD V{:X}, 0x{:02X}", x, kk),
+            LDxr { x } => write!(f, "LD V{:X}, R", x),
+            LDxt { x } => write!(f, "LD V{:X}, DT", x),
+            LDxy { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            LOW => write!(f, "LOW"),
+            OR { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            PLANE { n } => write!(f, "PLANE {}", n),
+            RET => write!(f, "RET"),
+            RND { x, kk } => write!(f, "RND V{:X}, 0x{:02X}", x, kk),
+            SCDn { n } => write!(f, "SCD {}", n),
+            SCL => write!(f, "SCL"),
+            SCR => write!(f, "SCR"),
+            SExkk { x, kk } => write!(f, "SE V{:X}, 0x{:02X}", x, kk),
+            SExy { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            SHL { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SHR { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            SKNP { x } => write!(f, "SKNP V{:X}", x),
+            SKP { x } => write!(f, "SKP V{:X}", x),
+            SNExkk { x, kk } => write!(f, "SNE V{:X}, 0x{:02X}", x, kk),
+            SNExy { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            SUB { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            SUBN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            XOR { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let text = |opcode| Instruction::from_opcode(opcode).to_string();
+        assert_eq!(text(0x00E0), "CLS");
+        assert_eq!(text(0x6A0F), "LD VA, 0x0F");
+        assert_eq!(text(0xA2F0), "LD I, 0x2F0");
+        assert_eq!(text(0xD125), "DRW V1, V2, 5");
+        assert_eq!(text(0xF155), "LD [I], V1");
+        assert_eq!(text(0xF301), "PLANE 3");
+        assert_eq!(text(0xF401), "DW 0xF401");
+        assert_eq!(text(0x0123), "DW 0x0123");
+    }
+
+    #[test]
+    fn decode_long() {
+        let memory = [0xF0, 0x00, 0x12, 0x34, 0xF0, 0x00];
+        let instruction = Instruction::decode(&memory, 0).unwrap();
+        assert_eq!(instruction.to_string(), "LD I, long 0x1234");
+        assert_eq!(instruction.size(), 4);
+        assert_eq!(Instruction::decode(&memory, 2).unwrap().to_string(), "JP 0x234");
+        assert!(Instruction::decode(&memory, 4).is_none());
+    }
+}
@@ -0,0 +1,178 @@
+use chip8::{rom, screenshot, Database};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+const PAGE_SIZE: usize = 10;
+
+pub struct Entry {
+    pub path: PathBuf,
+    // The name from the ROM database, or else the file name.
+    pub title: String,
+    pub details: Vec<String>,
+    pub thumbnail: Option<PathBuf>,
+}
+
+impl Entry {
+    fn new(path: PathBuf, database: &Database, screenshots: Option<&Path>) -> Self {
+        let file_name = path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+        let stem = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        let mut details = vec![file_name];
+        let mut title = None;
+
+        match rom::read(&path) {
+            Ok(rom) => {
+                details.push(format!("{} bytes", rom.data.len()));
+                match database.lookup(&rom.data) {
+                    Ok(info) => {
+                        title = info.name;
+                        if let Some(platform) = info.platform {
+                            details.push(format!("{:?}", platform).to_lowercase());
+                        }
+                        if let Some(hz) = info.hz {
+                            details.push(format!("{} hz", hz));
+                        }
+                    }
+                    Err(error) => details.push(error),
+                }
+            }
+            Err(error) => details.push(error),
+        }
+
+        Entry {
+            title: title.unwrap_or_else(|| stem.clone()),
+            details,
+            thumbnail: screenshots.and_then(|dir| screenshot::latest(dir, &stem)),
+            path,
+        }
+    }
+}
+
+// A list of the ROMs in a directory or zip archive to pick one from. The
+// ROMs in archives within the directory are listed alongside the others.
+pub struct Launcher {
+    entries: Vec<Entry>,
+    selected: usize,
+}
+
+impl Launcher {
+    pub fn new(dir: &Path, database: &Database) -> Result<Self, String> {
+        let mut paths = Vec::new();
+        if rom::is_archive(dir) {
+            paths = rom::archive_entries(dir)?;
+        } else {
+            let error = |error: ::std::io::Error| format!("could not list {}: {}", dir.display(), error);
+            for entry in fs::read_dir(dir).map_err(error)? {
+                let path = entry.map_err(error)?.path();
+                let hidden = path.file_name().is_none_or(|name| name.to_string_lossy().starts_with('.'));
+                if !path.is_file() || hidden {
+                    continue;
+                }
+                // An archive that can't be read is listed by itself, to show
+                // the error when it is picked.
+                match rom::is_archive(&path).then(|| rom::archive_entries(&path)) {
+                    Some(Ok(entries)) => paths.extend(entries),
+                    _ => paths.push(path),
+                }
+            }
+        }
+
+        if paths.is_empty() {
+            return Err(format!("no ROMs in {}", dir.display()));
+        }
+        paths.sort();
+
+        let screenshots = screenshot::directory();
+        Ok(Launcher {
+            entries: paths.into_iter().map(|path| Entry::new(path, database, screenshots.as_deref())).collect(),
+            selected: 0,
+        })
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_entry(&self) -> &Entry {
+        &self.entries[self.selected]
+    }
+
+    // Moves the selection with the arrow keys or a controller, returning the
+    // ROM picked with return.
+    pub fn input(&mut self, name: &str, pressed: bool) -> Option<PathBuf> {
+        if !pressed {
+            return None;
+        }
+
+        let last = self.entries.len() - 1;
+        match name {
+            "up" | "pad_up" => self.selected = self.selected.saturating_sub(1),
+            "down" | "pad_down" => self.selected = (self.selected + 1).min(last),
+            "pageup" | "pad_left" => self.selected = self.selected.saturating_sub(PAGE_SIZE),
+            "pagedown" | "pad_right" => self.selected = (self.selected + PAGE_SIZE).min(last),
+            "home" => self.selected = 0,
+            "end" => self.selected = last,
+            "return" | "pad_button0" => return Some(self.selected_entry().path.clone()),
+            _ => (),
+        }
+        None
+    }
+
+    pub fn visible(&self, rows: usize) -> Range<usize> {
+        visible(self.selected, self.entries.len(), rows)
+    }
+}
+
+// The items of a list to show when there is room for `rows` of them, keeping
+// the selected one in view.
+pub fn visible(selected: usize, count: usize, rows: usize) -> Range<usize> {
+    let start = selected.saturating_sub(rows / 2).min(count.saturating_sub(rows));
+    start..(start + rows).min(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn launcher(count: usize) -> Launcher {
+        let entry = |index| Entry {
+            path: PathBuf::from(format!("{}", index)),
+            title: String::new(),
+            details: Vec::new(),
+            thumbnail: None,
+        };
+        Launcher {
+            entries: (0..count).map(entry).collect(),
+            selected: 0,
+        }
+    }
+
+    #[test]
+    fn input() {
+        let mut launcher = launcher(20);
+        assert_eq!(launcher.input("up", true), None);
+        assert_eq!(launcher.selected(), 0);
+        launcher.input("pagedown", true);
+        launcher.input("down", true);
+        launcher.input("down", false);
+        assert_eq!(launcher.selected(), 11);
+        launcher.input("end", true);
+        launcher.input("down", true);
+        assert_eq!(launcher.input("return", true), Some(PathBuf::from("19")));
+    }
+
+    #[test]
+    fn visible() {
+        let mut launcher = launcher(20);
+        assert_eq!(launcher.visible(5), 0..5);
+        launcher.selected = 10;
+        assert_eq!(launcher.visible(5), 8..13);
+        launcher.selected = 19;
+        assert_eq!(launcher.visible(5), 15..20);
+        assert_eq!(launcher.visible(30), 0..20);
+    }
+}
@@ -0,0 +1,40 @@
+extern crate gif;
+extern crate image;
+extern crate inflate;
+extern crate rand;
+
+pub mod bindings;
+pub mod cartridge;
+pub mod cheat;
+pub mod chip8;
+pub mod config;
+pub mod coverage;
+pub mod database;
+pub mod display;
+pub mod filter;
+pub mod flow;
+pub mod font;
+pub mod instruction;
+pub mod octo;
+pub mod palette;
+pub mod platform;
+pub mod profiler;
+pub mod rom;
+pub mod scheduler;
+pub mod screenshot;
+pub mod sha1;
+pub mod sprites;
+pub mod zip;
+
+pub use bindings::{Action, Bindings};
+pub use cheat::{Cheats, Condition, Search};
+pub use chip8::{rom_warning, Chip8, CodeWrite, Halt, RomError, State, VIP_CYCLES_PER_FRAME};
+pub use config::Config;
+pub use database::{Database, RomInfo};
+pub use display::{Display, Resolution, HEIGHT, WIDTH};
+pub use filter::{Filter, Phosphor};
+pub use flow::FlowGraph;
+pub use instruction::Instruction;
+pub use palette::Palette;
+pub use platform::{Platform, Quirks};
+pub use scheduler::{Scheduler, Timing};
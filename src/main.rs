@@ -1,32 +1,258 @@
-extern crate structopt;
+extern crate chip8;
+extern crate image;
 extern crate piston_window;
-extern crate rand;
+extern crate structopt;
+#[cfg(unix)]
+extern crate termion;
 
-mod chip8;
-mod instruction;
+mod app;
+mod emulator;
+mod gamepad;
+mod launcher;
+mod memory;
+mod menu;
+#[cfg(unix)]
+mod tty;
 
 use chip8::*;
+// Piston has a texture `Filter` of its own.
+use chip8::Filter;
+use app::{App, Settings};
+use emulator::Emulator;
+use gamepad::Gamepad;
+use image::RgbaImage;
+use launcher::Launcher;
+use memory::{Mark, MemoryView};
+use menu::CheatMenu;
 use piston_window::*;
+use std::fs;
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
 
-const PIXEL_SIZE: usize = 10;
+const DEFAULT_FAST_FORWARD: f64 = 4.0;
+// A minute of play when looking for sprites.
+const SPRITE_FRAMES: u32 = 3600;
+// Lines of text in lists, with a pixel of space above and below.
+const ROW_HEIGHT: usize = font::GLYPH_HEIGHT + 2;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "chip8")]
 struct Opt {
-    #[structopt(name = "ROM", parse(from_os_str))]
-    rom: std::path::PathBuf,
-    #[structopt(short = "s", long = "speed", default_value = "1")]
-    speed: u8,
+    #[structopt(name = "ROM", parse(from_os_str), help = "The ROM to run, or leave out to pick one from the launcher")]
+    rom: Option<std::path::PathBuf>,
+    #[structopt(long = "games", parse(from_os_str), default_value = "games", help = "Directory of ROMs listed by the launcher")]
+    games: std::path::PathBuf,
+    #[structopt(short = "s", long = "speed", default_value = "1", parse(try_from_str = "parse_multiplier"), help = "Speed multiplier from 1/16 to 16, fractions slow the game down")]
+    speed: f64,
+    #[structopt(long = "fast-forward", parse(try_from_str = "parse_multiplier"), help = "Speed multiplier while the fast forward key is held [default: 4]")]
+    fast_forward: Option<f64>,
+    #[structopt(long = "hz", help = "Instructions per second, 1000 is common for SCHIP [default: 500]")]
+    hz: Option<f64>,
+    #[structopt(short = "p", long = "platform", help = "Quirks to emulate: chip8, schip or xochip [default: xochip]")]
+    platform: Option<Platform>,
+    #[structopt(short = "q", long = "quirk", raw(number_of_values = "1"), help = "Override a quirk of the platform, e.g. clip_sprites=false")]
+    quirks: Vec<String>,
+    #[structopt(long = "palette", help = "Palette name (yellow, green, amber, white, lcd, octo) or two to four rrggbb colours")]
+    palette: Option<Palette>,
+    #[structopt(long = "filter", help = "Anti-flicker filter: none, blend:<frames> or fade:<0.0-1.0> [default: none]")]
+    filter: Option<Filter>,
+    #[structopt(long = "config", parse(from_os_str), help = "Configuration file [default: ~/.config/chip8/config]")]
+    config: Option<std::path::PathBuf>,
+    #[structopt(long = "vip-timing", help = "Budget COSMAC VIP machine cycles per frame instead of a flat instruction count")]
+    vip_timing: bool,
+    #[structopt(long = "scale", default_value = "10", help = "Initial window size in window pixels per high resolution pixel")]
+    scale: u32,
+    #[structopt(long = "integer-scaling", help = "Only scale the display by whole multiples when the window is resized")]
+    integer_scaling: bool,
+    #[structopt(long = "tty", help = "Run in the terminal using half block characters")]
+    tty: bool,
+    #[structopt(long = "braille", help = "Run in the terminal using braille characters")]
+    braille: bool,
+    #[structopt(long = "sprites", parse(from_os_str), help = "Save the sprites the ROM draws to a PNG sheet and exit")]
+    sprites: Option<PathBuf>,
+    #[structopt(long = "flow", parse(from_os_str), help = "Save the control flow graph of the ROM as Graphviz DOT, and its call graph next to it, and exit")]
+    flow: Option<PathBuf>,
+    #[structopt(long = "profile", parse(from_os_str), help = "Count where the ROM spends its time and save a report on exit")]
+    profile: Option<PathBuf>,
+    #[structopt(long = "coverage", parse(from_os_str), help = "Track which memory was run, read and written, saving a heatmap PNG and an annotated disassembly next to it on exit")]
+    coverage: Option<PathBuf>,
+    #[structopt(long = "trace-code-writes", help = "Print whenever the ROM writes over its own code")]
+    trace_code_writes: bool,
+    #[structopt(long = "frames", help = "Run this many frames without a display and exit, e.g. to profile a test ROM")]
+    frames: Option<u32>,
 }
 
 fn main() {
     let opt = Opt::from_args();
-    let bytes = &std::fs::read(opt.rom).unwrap();
-    let mut chip8 = Chip8::new(bytes);
 
-    let window_settings = WindowSettings::new("Chip8", [(chip8::WIDTH * PIXEL_SIZE) as u32, (chip8::HEIGHT * PIXEL_SIZE) as u32]);
-    let mut window: PistonWindow = window_settings.exit_on_esc(true).build().unwrap();
+    let config_dir = config::config_dir();
+    let config_path = opt.config.clone().or_else(|| config_dir.as_ref().map(|dir| dir.join("config")));
+    let config = config_path.map_or(Ok(Config::default()), |path| Config::load(&path)).unwrap_or_else(|error| exit(&error));
+    let database = config_dir.map_or(Ok(Database::bundled()), |dir| Database::load(&dir.join("roms.cfg"))).unwrap_or_else(|error| exit(&error));
+
+    let filter = match opt.filter {
+        Some(filter) => filter,
+        None => config.get("display", "filter").map_or(Ok(Filter::None), str::parse).unwrap_or_else(|error| exit(&error)),
+    };
+    let fast_forward = match opt.fast_forward {
+        Some(multiplier) => multiplier,
+        None => config.get("speed", "fast_forward").map_or(Ok(DEFAULT_FAST_FORWARD), parse_multiplier).unwrap_or_else(|error| exit(&format!("invalid fast_forward multiplier: {}", error))),
+    };
+
+    let settings = Settings {
+        config,
+        database,
+        platform: opt.platform,
+        quirks: opt.quirks.clone(),
+        hz: opt.hz,
+        palette: opt.palette,
+        filter,
+        vip_timing: opt.vip_timing,
+        speed: opt.speed,
+        fast_forward,
+        profile: opt.profile.is_some(),
+        coverage: opt.coverage.is_some(),
+    };
+    let mut app = App::new(settings, opt.games.clone()).unwrap_or_else(|error| exit(&error));
+    match opt.rom {
+        Some(ref rom) => {
+            if let Some(warning) = app.open(rom).unwrap_or_else(|error| exit(&error)) {
+                eprintln!("chip8: {}", warning);
+            }
+        }
+        None if opt.sprites.is_some() || opt.flow.is_some() || opt.frames.is_some() => exit("a ROM is needed to run without a display"),
+        None => app.show_launcher().unwrap_or_else(|error| exit(&error)),
+    }
+
+    if opt.sprites.is_some() || opt.flow.is_some() {
+        let emulator = app.emulator.as_mut().unwrap_or_else(|| exit("the archive holds more than one ROM, pick one with ARCHIVE/NAME"));
+        if let Some(ref path) = opt.flow {
+            save_flow(emulator, path);
+        }
+        if let Some(ref path) = opt.sprites {
+            save_sprites(emulator, path);
+        }
+        return;
+    }
+
+    if let Some(frames) = opt.frames {
+        for _ in 0..frames {
+            app.update(1.0 / scheduler::FRAMES_PER_SECOND);
+            print_code_writes(&mut app, opt.trace_code_writes);
+            if let Some(halt) = app.take_halt() {
+                eprintln!("chip8: {}", halt);
+                break;
+            }
+        }
+    } else if opt.tty || opt.braille {
+        run_tty(&mut app, opt.braille);
+    } else {
+        run_window(&mut app, opt.scale.max(1), opt.integer_scaling, opt.trace_code_writes);
+    }
+
+    if let Some(ref path) = opt.profile {
+        save_profile(&app, path);
+    }
+    if let Some(ref path) = opt.coverage {
+        save_coverage(&app, path);
+    }
+}
+
+// Saves the coverage heatmap of the ROM that was running last to `path`,
+// and its annotated disassembly next to it as a text file.
+fn save_coverage(app: &App, path: &Path) {
+    let emulator = match app.emulator {
+        Some(ref emulator) => emulator,
+        None => return,
+    };
+    if let Some(ref coverage) = emulator.coverage {
+        let failed = |path: &Path, error: &dyn std::fmt::Display| exit(&format!("could not save {}: {}", path.display(), error));
+        coverage.heatmap().save(path).unwrap_or_else(|error| failed(path, &error));
+        let text = path.with_extension("txt");
+        fs::write(&text, coverage.annotate(emulator.chip8.get_memory())).unwrap_or_else(|error| failed(&text, &error));
+        println!("saved the coverage to {} and {}", path.display(), text.display());
+    }
+}
+
+// Saves the profile of the ROM that was running last.
+fn save_profile(app: &App, path: &Path) {
+    let emulator = match app.emulator {
+        Some(ref emulator) => emulator,
+        None => return,
+    };
+    if let Some(ref profiler) = emulator.profiler {
+        let report = profiler.report(emulator.chip8.get_memory());
+        fs::write(path, report).unwrap_or_else(|error| exit(&format!("could not save {}: {}", path.display(), error)));
+        println!("saved the profile to {}", path.display());
+    }
+}
+
+// Saves the control flow graph of the ROM to `path` and its call graph next
+// to it, before anything has run.
+fn save_flow(emulator: &Emulator, path: &Path) {
+    let graph = FlowGraph::new(emulator.chip8.get_memory());
+    let failed = |path: &Path, error: &dyn std::fmt::Display| exit(&format!("could not save {}: {}", path.display(), error));
+    fs::write(path, graph.to_dot()).unwrap_or_else(|error| failed(path, &error));
+    let calls = path.with_extension("calls.dot");
+    fs::write(&calls, graph.calls_to_dot()).unwrap_or_else(|error| failed(&calls, &error));
+    println!("saved {} blocks to {} and {} calls to {}", graph.blocks.len(), path.display(), graph.calls.len(), calls.display());
+
+    for block in graph.blocks.values() {
+        if block.computed_jump {
+            println!("computed jump at {:03X}", block.end.wrapping_sub(2));
+        }
+        for address in &block.code_writes {
+            println!("write over code at {:03X}", address);
+        }
+    }
+}
+
+// Runs the ROM without a display and saves the sprites it drew together
+// with those found in its code.
+fn save_sprites(emulator: &mut Emulator, path: &Path) {
+    let mut found = sprites::scan(emulator.chip8.get_memory());
+    found.extend(sprites::trace(&mut emulator.chip8, &mut emulator.scheduler, SPRITE_FRAMES));
+    if let Some(halt) = emulator.take_halt() {
+        eprintln!("chip8: stopped tracing, {}", halt);
+    }
+    found.sort();
+    found.dedup();
+
+    let sheet = sprites::sheet(emulator.chip8.get_memory(), &found, &emulator.palette);
+    sheet.save(path).unwrap_or_else(|error| exit(&format!("could not save {}: {}", path.display(), error)));
+    println!("saved {} sprites to {}", found.len(), path.display());
+}
+
+// Speed multipliers are limited to what the speed keys can reach.
+fn parse_multiplier(text: &str) -> Result<f64, String> {
+    match text.parse() {
+        Ok(multiplier) if (scheduler::MIN_SPEED..=scheduler::MAX_SPEED).contains(&multiplier) => Ok(multiplier),
+        _ => Err(format!("expected a multiplier from {} to {}, got {}", scheduler::MIN_SPEED, scheduler::MAX_SPEED, text)),
+    }
+}
+
+// Code writes pile up until taken, so they're taken even when not printed.
+fn print_code_writes(app: &mut App, trace: bool) {
+    for write in app.take_code_writes() {
+        if trace {
+            println!("{}", write);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn run_tty(app: &mut App, braille: bool) {
+    tty::run(app, braille).unwrap_or_else(|error| exit(&error.to_string()));
+}
+
+#[cfg(not(unix))]
+fn run_tty(_app: &mut App, _braille: bool) {
+    exit("the terminal frontend is only available on unix");
+}
+
+fn run_window(app: &mut App, scale: u32, integer_scaling: bool, trace_code_writes: bool) {
+    let window_settings = WindowSettings::new(app.title().to_string(), [chip8::WIDTH as u32 * scale, chip8::HEIGHT as u32 * scale]);
+    let mut window: PistonWindow = window_settings.exit_on_esc(true).resizable(true).build().unwrap();
 
     let mut event_settings = window.get_event_settings();
     event_settings.set_ups(100);
@@ -34,85 +260,289 @@ fn main() {
     event_settings.set_max_fps(60);
     window.set_event_settings(event_settings);
 
-    let mut step = false;
-    let mut next = false;
+    // The display is drawn as a single texture, only uploaded again when the
+    // filtered pixels or the palette changed.
+    let mut canvas = RgbaImage::new(chip8::WIDTH as u32, chip8::HEIGHT as u32);
+    let texture_settings = TextureSettings::new().filter(piston_window::Filter::Nearest);
+    let mut texture = Texture::from_image(&mut window.factory, &canvas, &texture_settings).unwrap();
+    let mut texture_palette = None;
+    // The launcher's thumbnail of the selected ROM, loaded when the
+    // selection changes.
+    let mut thumbnail: Option<(PathBuf, Option<G2dTexture>)> = None;
+
+    let mut gamepad = Gamepad::open();
+    let mut fullscreen = false;
+    let mut title = app.title().to_string();
 
     while let Some(event) = window.next() {
-        if let Some(_render) = event.render_args() {
+        if let Some(render) = event.render_args() {
+            let palette = app.palette();
+
+            if let Some(ref launcher) = app.launcher {
+                let path = launcher.selected_entry().thumbnail.clone();
+                if thumbnail.as_ref().map(|(loaded, _)| loaded) != path.as_ref() {
+                    thumbnail = path.map(|path| {
+                        let image = image::open(&path).ok().map(|image| image.to_rgba());
+                        let texture = image.and_then(|image| Texture::from_image(&mut window.factory, &image, &texture_settings).ok());
+                        (path, texture)
+                    });
+                }
+
+                let texture = thumbnail.as_ref().and_then(|(_, texture)| texture.as_ref());
+                window.draw_2d(&event, |context, graphics| {
+                    draw_launcher(launcher, texture, palette, (render.width, render.height), context, graphics);
+                });
+                continue;
+            }
+
+            let emulator = match app.emulator {
+                Some(ref mut emulator) => emulator,
+                None => continue,
+            };
+
+            if let Some(ref menu) = app.menu {
+                window.draw_2d(&event, |context, graphics| {
+                    draw_menu(menu, emulator, palette, (render.width, render.height), context, graphics);
+                });
+                continue;
+            }
+
+            if let Some(ref view) = app.memory {
+                window.draw_2d(&event, |context, graphics| {
+                    draw_memory(view, &emulator.chip8, palette, (render.width, render.height), context, graphics);
+                });
+                continue;
+            }
+
+            if emulator.phosphor.take_changed() || texture_palette != Some(palette) {
+                screenshot::paint(&emulator.phosphor, &palette, &mut canvas);
+                texture.update(&mut window.encoder, &canvas).unwrap();
+                texture_palette = Some(palette);
+            }
+
+            let (width, height) = emulator.phosphor.get_size();
+            let (pixel_size, left, top) = letterbox((render.width, render.height), (width, height), integer_scaling);
+            let texel_size = pixel_size * width as f64 / chip8::WIDTH as f64;
+
+            let indicator = emulator.indicator();
             window.draw_2d(&event, |context, graphics| {
                 clear([0.0, 0.0, 0.0, 1.0], graphics);
+                image(&texture, context.transform.trans(left, top).scale(texel_size, texel_size), graphics);
 
-                chip8.get_display().iter().enumerate().for_each(|(index, &bit)| {
-                    let (x, y) = (index % chip8::WIDTH as usize, index / chip8::WIDTH as usize);
-                    let color = if bit { [1.0, 1.0, 0.0, 1.0] } else { [0.0, 0.0, 0.0, 1.0] };
-                    let position = [(x * PIXEL_SIZE as usize) as f64, (y * PIXEL_SIZE as usize) as f64, PIXEL_SIZE as f64, PIXEL_SIZE as f64];
-                    rectangle(color, position, context.transform, graphics);
-                });
+                if let Some(text) = indicator {
+                    // Text pixels are the size of high resolution pixels, but
+                    // at least two window pixels.
+                    let dot = texel_size.max(2.0).round();
+                    let transform = context.transform.trans(left + dot, top + dot);
+                    let box_size = [(font::text_width(&text) + 2) as f64 * dot, (font::GLYPH_HEIGHT + 2) as f64 * dot];
+                    rectangle(palette.background(), [0.0, 0.0, box_size[0], box_size[1]], transform, graphics);
+                    draw_text(&text, dot, palette.foreground(), transform.trans(dot, dot), graphics);
+                }
             });
         }
 
-        if let Some(press) = event.press_args() {
-            if let Button::Keyboard(key) = press {
-                if let Some(index) = match_key(key) {
-                    chip8.apply_keypad_value(index, true)
-                }
+        let mut inputs = Vec::new();
 
-                match key {
-                    Key::Space => if step { next = true },
-                    _ => (),
-                }
+        if let Some(Button::Keyboard(key)) = event.press_args() {
+            inputs.push((key_name(key), true));
+        }
+        if let Some(Button::Keyboard(key)) = event.release_args() {
+            inputs.push((key_name(key), false));
+        }
+        inputs.extend(gamepad.poll());
+
+        for (name, pressed) in inputs {
+            if let Some(message) = app.input(&name, pressed) {
+                println!("{}", message);
             }
         }
 
-        if let Some(release) = event.release_args() {
-            if let Button::Keyboard(key) = release {
-                if let Some(index) = match_key(key) {
-                    chip8.apply_keypad_value(index, false)
-                }
+        if app.fullscreen != fullscreen {
+            fullscreen = app.fullscreen;
+            let glutin = &window.window.window;
+            glutin.set_fullscreen(if fullscreen { Some(glutin.get_current_monitor()) } else { None });
+        }
 
-                match key {
-                    Key::LShift => step = !step,
-                    Key::Tab => chip8 = Chip8::new(bytes),
-                    _ => (),
-                }
+        if app.title() != title {
+            title = app.title().to_string();
+            window.set_title(title.clone());
+        }
+
+        if let Some(update) = event.update_args() {
+            if let Some(instruction) = app.update(update.dt) {
+                println!("{:?}", instruction);
+            }
+            print_code_writes(app, trace_code_writes);
+            if let Some(halt) = app.take_halt() {
+                println!("{}", halt);
             }
         }
+    }
+}
 
-        if let Some(_update) = event.update_args() {
-            if step {
-                if next {
-                    let instruction = chip8.tick();
-                    println!("{:?}", instruction);
-                    next = false;
-                }
-            } else {
-                for _i in 0..(opt.speed * 5) {
-                    chip8.tick();
-                }
-                chip8.decrement_counter();
+// Lists the ROMs on the left, with the thumbnail and details of the selected
+// one on the right.
+fn draw_launcher(launcher: &Launcher, thumbnail: Option<&G2dTexture>, palette: Palette, size: (u32, u32), context: Context, graphics: &mut G2d) {
+    let (width, height) = (size.0 as f64, size.1 as f64);
+    let (dot, rows) = list_size(height);
+    let half = (width / 2.0).floor();
+
+    clear(palette.background(), graphics);
+
+    let titles = launcher.visible(rows).map(|index| (launcher.entries()[index].title.as_str(), index == launcher.selected()));
+    draw_list(titles, dot, half, palette, context, graphics);
+
+    let entry = launcher.selected_entry();
+    let margin = 2.0 * dot;
+    let thumbnail_width = width - half - 2.0 * margin;
+    let mut top = ROW_HEIGHT as f64 * dot;
+    if let Some(texture) = thumbnail {
+        let (texture_width, texture_height) = texture.get_size();
+        let scale = thumbnail_width / texture_width as f64;
+        image(texture, context.transform.trans(half + margin, top).scale(scale, scale), graphics);
+        top += (texture_height as f64 * scale).ceil() + margin;
+    }
+    for line in entry.details.iter() {
+        draw_text(line, dot, palette.foreground(), context.transform.trans(half + margin, top), graphics);
+        top += ROW_HEIGHT as f64 * dot;
+    }
+}
+
+// Lists the cheats and the memory search in place of the display.
+fn draw_menu(menu: &CheatMenu, emulator: &Emulator, palette: Palette, size: (u32, u32), context: Context, graphics: &mut G2d) {
+    let (dot, rows) = list_size(size.1 as f64);
+    let lines = menu.lines(emulator);
+
+    clear(palette.background(), graphics);
+
+    let visible = launcher::visible(menu.selected(), lines.len(), rows).map(|index| (lines[index].as_str(), index == menu.selected()));
+    draw_list(visible, dot, size.0 as f64, palette, context, graphics);
+}
+
+// The hex view of memory with the bytes at the cursor drawn as sprites to its
+// right, 8 pixels wide and 16x16.
+fn draw_memory(view: &MemoryView, chip8: &Chip8, palette: Palette, size: (u32, u32), context: Context, graphics: &mut G2d) {
+    const CHARACTER: usize = font::GLYPH_WIDTH + 1;
+    let (dot, rows) = list_size(size.1 as f64);
+    let row_height = ROW_HEIGHT as f64 * dot;
+
+    clear(palette.background(), graphics);
+
+    let header = format!("{:04X}  PC {:03X}  I {:03X}", view.cursor(), chip8.get_pc(), chip8.get_i());
+    draw_text(&header, dot, palette.foreground(), context.transform.trans(2.0 * dot, dot), graphics);
+
+    let (row, count) = view.rows(chip8);
+    for (line, row) in launcher::visible(row, count, rows).enumerate() {
+        let top = (line + 1) as f64 * row_height;
+        let address = row * memory::ROW_SIZE;
+        draw_text(&format!("{:04X}", address), dot, palette.foreground(), context.transform.trans(2.0 * dot, top), graphics);
+
+        for (column, address) in (address..address + memory::ROW_SIZE).enumerate() {
+            let left = (2 + (5 + 3 * column) * CHARACTER) as f64 * dot;
+            let mut colour = palette.foreground();
+            let highlight = match view.mark(chip8, address) {
+                Mark::None => None,
+                Mark::Cursor => Some(palette.foreground()),
+                Mark::Pc => Some([0.2, 0.8, 0.2, 1.0]),
+                Mark::I => Some([0.2, 0.6, 1.0, 1.0]),
+                Mark::Written => Some([1.0, 0.3, 0.3, 1.0]),
+            };
+            if let Some(highlight) = highlight {
+                rectangle(highlight, [left - dot, top - dot, (2 * CHARACTER + 1) as f64 * dot, row_height], context.transform, graphics);
+                colour = palette.background();
             }
+            draw_text(&view.byte_text(chip8, address), dot, colour, context.transform.trans(left, top), graphics);
+        }
+    }
+
+    let pixel = 4.0 * dot;
+    let left = (2 + (6 + 3 * memory::ROW_SIZE) * CHARACTER) as f64 * dot;
+    draw_sprite(&view.sprite(chip8), 8, pixel, palette, context.transform.trans(left, row_height), graphics);
+    let left = left + 10.0 * pixel;
+    draw_sprite(&view.wide_sprite(chip8), 16, pixel, palette, context.transform.trans(left, row_height), graphics);
+}
+
+// Draws sprite rows with the leftmost pixel in the highest bit, framed so
+// that blank rows show too.
+fn draw_sprite(rows: &[u16], width: usize, pixel: f64, palette: Palette, transform: math::Matrix2d, graphics: &mut G2d) {
+    let frame = [-1.0, -1.0, width as f64 * pixel + 2.0, rows.len() as f64 * pixel + 2.0];
+    Rectangle::new_border(palette.foreground(), 0.5).draw(frame, &DrawState::default(), transform, graphics);
+    for (y, bits) in rows.iter().enumerate() {
+        for x in (0..width).filter(|x| bits & (0x8000 >> x) != 0) {
+            rectangle(palette.foreground(), [x as f64 * pixel, y as f64 * pixel, pixel, pixel], transform, graphics);
+        }
+    }
+}
+
+// The size of a font pixel for lists in a window this high, fitting at least
+// 24 rows of text, and the number of rows that fit below a blank one.
+fn list_size(height: f64) -> (f64, usize) {
+    let dot = (height / (ROW_HEIGHT * 24) as f64).floor().max(1.0);
+    (dot, ((height / dot) as usize / ROW_HEIGHT).saturating_sub(2).max(1))
+}
+
+// Draws rows of text from the second row down, cut to `width`, with the
+// selected one highlighted.
+fn draw_list<'a, I: Iterator<Item = (&'a str, bool)>>(rows: I, dot: f64, width: f64, palette: Palette, context: Context, graphics: &mut G2d) {
+    let columns = (width / dot) as usize / (font::GLYPH_WIDTH + 1);
+    for (row, (text, selected)) in rows.enumerate() {
+        let text: String = text.chars().take(columns.saturating_sub(2)).collect();
+        let top = ((row + 1) * ROW_HEIGHT) as f64 * dot;
+        let mut colour = palette.foreground();
+        if selected {
+            rectangle(colour, [0.0, top - dot, width, ROW_HEIGHT as f64 * dot], context.transform, graphics);
+            colour = palette.background();
         }
+        draw_text(&text, dot, colour, context.transform.trans(2.0 * dot, top), graphics);
     }
 }
 
-fn match_key(key: Key) -> Option<u8> {
-    match key {
-        Key::D1 => Some(0x1),
-        Key::D2 => Some(0x2),
-        Key::D3 => Some(0x3),
-        Key::D4 => Some(0xC),
-        Key::Q => Some(0x4),
-        Key::W => Some(0x5),
-        Key::E => Some(0x6),
-        Key::R => Some(0xD),
-        Key::A => Some(0x7),
-        Key::S => Some(0x8),
-        Key::D => Some(0x9),
-        Key::F => Some(0xE),
-        Key::Z => Some(0xA),
-        Key::X => Some(0x0),
-        Key::C => Some(0xB),
-        Key::V => Some(0xF),
-        _ => None,
+// Draws text in the 3x5 font with its top left corner at the origin of
+// `transform`, each font pixel `dot` window pixels in size.
+fn draw_text(text: &str, dot: f64, colour: [f32; 4], transform: math::Matrix2d, graphics: &mut G2d) {
+    font::layout(text, |x, y| rectangle(colour, [x as f64 * dot, y as f64 * dot, dot, dot], transform, graphics));
+}
+
+// The size of a display pixel and the top left corner of the display when
+// it is fitted into the window, keeping its aspect ratio. With integer
+// scaling every display pixel covers the same number of window pixels.
+fn letterbox(window: (u32, u32), display: (usize, usize), integer_scaling: bool) -> (f64, f64, f64) {
+    let (window_width, window_height) = (window.0 as f64, window.1 as f64);
+    let (width, height) = (display.0 as f64, display.1 as f64);
+
+    let mut pixel_size = (window_width / width).min(window_height / height);
+    if integer_scaling && pixel_size >= 1.0 {
+        pixel_size = pixel_size.floor();
+    }
+
+    let left = ((window_width - width * pixel_size) / 2.0).floor();
+    let top = ((window_height - height * pixel_size) / 2.0).floor();
+    (pixel_size, left, top)
+}
+
+// The name of a key in the `[keys]` configuration, e.g. `q`, `1` or `lshift`.
+fn key_name(key: Key) -> String {
+    let name = format!("{:?}", key).to_lowercase();
+    match name.as_bytes() {
+        [b'd', digit] if digit.is_ascii_digit() => (*digit as char).to_string(),
+        _ => name,
+    }
+}
+
+fn exit(error: &str) -> ! {
+    eprintln!("chip8: {}", error);
+    std::process::exit(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn letterbox_keeps_aspect_ratio() {
+        assert_eq!(letterbox((1280, 640), (64, 32), false), (20.0, 0.0, 0.0));
+        assert_eq!(letterbox((1280, 1000), (64, 32), false), (20.0, 0.0, 180.0));
+        assert_eq!(letterbox((1000, 640), (128, 64), false), (1000.0 / 128.0, 0.0, 70.0));
+        assert_eq!(letterbox((1000, 640), (128, 64), true), (7.0, 52.0, 96.0));
     }
 }
@@ -0,0 +1,203 @@
+use chip8::Chip8;
+
+// Bytes in a row of the hex view.
+pub const ROW_SIZE: usize = 8;
+const PAGE_ROWS: usize = 16;
+// DRW draws up to 15 rows of 8 pixels, DRWH 16 rows of 16.
+const SPRITE_HEIGHT: usize = 15;
+const WIDE_SPRITE_SIZE: usize = 16;
+
+// Why a byte stands out, the first that applies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mark {
+    None,
+    Cursor,
+    Pc,
+    I,
+    Written,
+}
+
+// A hex view of memory shown in place of the display, while the emulator
+// keeps running. Return toggles editing, during which the arrow keys, page up
+// and page down move the cursor, home and end jump to the PC and I, and two
+// hex digits overwrite the byte at the cursor. Other inputs, and all of them
+// while not editing, go to the emulator as usual.
+#[derive(Default)]
+pub struct MemoryView {
+    cursor: usize,
+    editing: bool,
+    // The high nibble typed so far.
+    pending: Option<u8>,
+    // Inputs pressed while the view took them, whose releases it takes too.
+    held: Vec<String>,
+}
+
+impl MemoryView {
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn editing(&self) -> bool {
+        self.editing
+    }
+
+    // A byte in hex, or the digit typed so far at the cursor.
+    pub fn byte_text(&self, chip8: &Chip8, address: usize) -> String {
+        match self.pending {
+            Some(high) if address == self.cursor => format!("{:X}_", high),
+            _ => format!("{:02X}", chip8.get_memory()[address]),
+        }
+    }
+
+    pub fn mark(&self, chip8: &Chip8, address: usize) -> Mark {
+        let pc = chip8.get_pc() as usize;
+        if address == self.cursor && self.editing {
+            Mark::Cursor
+        } else if address == pc || address == pc + 1 {
+            Mark::Pc
+        } else if address == chip8.get_i() as usize {
+            Mark::I
+        } else if chip8.get_recent_writes().contains(&address) {
+            Mark::Written
+        } else {
+            Mark::None
+        }
+    }
+
+    // The row at the cursor and the number of rows in the view.
+    pub fn rows(&self, chip8: &Chip8) -> (usize, usize) {
+        (self.cursor / ROW_SIZE, chip8.get_memory().len() / ROW_SIZE)
+    }
+
+    // The bytes at the cursor drawn as a DRW sprite, one byte per row.
+    pub fn sprite(&self, chip8: &Chip8) -> Vec<u16> {
+        let memory = chip8.get_memory();
+        (0..SPRITE_HEIGHT).map(|row| u16::from(memory.get(self.cursor + row).cloned().unwrap_or(0)) << 8).collect()
+    }
+
+    // The bytes at the cursor drawn as a 16x16 DRWH sprite, two bytes per
+    // row.
+    pub fn wide_sprite(&self, chip8: &Chip8) -> Vec<u16> {
+        let memory = chip8.get_memory();
+        let byte = |address: usize| u16::from(memory.get(address).cloned().unwrap_or(0));
+        (0..WIDE_SPRITE_SIZE).map(|row| byte(self.cursor + 2 * row) << 8 | byte(self.cursor + 2 * row + 1)).collect()
+    }
+
+    // Returns whether the input was used by the view.
+    pub fn input(&mut self, name: &str, pressed: bool, chip8: &mut Chip8) -> bool {
+        if !pressed {
+            let held = self.held.len();
+            self.held.retain(|other| other != name);
+            return self.held.len() < held;
+        }
+        if name == "return" {
+            self.editing = !self.editing;
+            self.pending = None;
+        } else if !self.editing || !self.edit(name, chip8) {
+            return false;
+        }
+        if !self.held.iter().any(|other| other == name) {
+            self.held.push(name.to_string());
+        }
+        true
+    }
+
+    // Returns whether the key is one editing uses.
+    fn edit(&mut self, name: &str, chip8: &mut Chip8) -> bool {
+        let last = chip8.get_memory().len() - 1;
+        let digit = match u8::from_str_radix(name, 16) {
+            Ok(digit) if name.len() == 1 => Some(digit),
+            _ => None,
+        };
+        let cursor = match name {
+            "left" => self.cursor.saturating_sub(1),
+            "right" => self.cursor + 1,
+            "up" => self.cursor.saturating_sub(ROW_SIZE),
+            "down" => self.cursor + ROW_SIZE,
+            "pageup" => self.cursor.saturating_sub(ROW_SIZE * PAGE_ROWS),
+            "pagedown" => self.cursor + ROW_SIZE * PAGE_ROWS,
+            "home" => chip8.get_pc() as usize,
+            "end" => chip8.get_i() as usize,
+            "backspace" => self.cursor,
+            _ if digit.is_some() => self.cursor,
+            _ => return false,
+        };
+
+        match (self.pending, digit) {
+            (None, Some(digit)) => self.pending = Some(digit),
+            (Some(high), Some(low)) => {
+                chip8.set_memory(self.cursor, high << 4 | low);
+                self.pending = None;
+                self.cursor = (self.cursor + 1).min(last);
+            }
+            _ => {
+                self.pending = None;
+                self.cursor = cursor.min(last);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn edit() {
+        let mut chip8 = Chip8::new(&[0x00, 0xE0]).unwrap();
+        let mut view = MemoryView::default();
+        assert!(!view.input("space", true, &mut chip8));
+        assert!(!view.input("home", true, &mut chip8));
+        assert!(view.input("return", true, &mut chip8));
+        assert!(view.input("return", false, &mut chip8));
+        assert!(view.editing());
+
+        view.input("home", true, &mut chip8);
+        view.input("down", true, &mut chip8);
+        assert_eq!(view.cursor(), 0x208);
+        view.input("a", true, &mut chip8);
+        view.input("a", false, &mut chip8);
+        assert_eq!(view.byte_text(&chip8, 0x208), "A_");
+        view.input("5", true, &mut chip8);
+        assert_eq!(chip8.get_memory()[0x208], 0xA5);
+        assert_eq!(view.cursor(), 0x209);
+
+        view.input("3", true, &mut chip8);
+        view.input("backspace", true, &mut chip8);
+        assert_eq!(view.byte_text(&chip8, 0x209), "00");
+        assert_eq!(view.mark(&chip8, 0x201), Mark::Pc);
+        assert_eq!(view.mark(&chip8, 0x209), Mark::Cursor);
+
+        view.input("return", true, &mut chip8);
+        assert!(!view.input("a", true, &mut chip8));
+        assert_eq!(view.mark(&chip8, 0x209), Mark::None);
+    }
+
+    // Keys held down when editing starts still reach the emulator when
+    // they're released, rather than staying pressed on the keypad.
+    #[test]
+    fn releases() {
+        let mut chip8 = Chip8::new(&[0x00, 0xE0]).unwrap();
+        let mut view = MemoryView::default();
+        assert!(!view.input("1", true, &mut chip8));
+        view.input("return", true, &mut chip8);
+        assert!(!view.input("1", false, &mut chip8));
+
+        assert!(view.input("2", true, &mut chip8));
+        view.input("return", true, &mut chip8);
+        assert!(view.input("2", false, &mut chip8));
+        assert!(!view.input("2", true, &mut chip8));
+        assert!(!view.input("2", false, &mut chip8));
+    }
+
+    #[test]
+    fn sprites() {
+        let mut chip8 = Chip8::new(&[0x00, 0xE0, 0xF0, 0x90]).unwrap();
+        let mut view = MemoryView::default();
+        view.input("return", true, &mut chip8);
+        view.input("home", true, &mut chip8);
+        assert_eq!(view.sprite(&chip8)[..4], [0x0000, 0xE000, 0xF000, 0x9000]);
+        assert_eq!(view.wide_sprite(&chip8)[..3], [0x00E0, 0xF090, 0x0000]);
+    }
+}
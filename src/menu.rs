@@ -0,0 +1,141 @@
+use chip8::{Condition, Search};
+use emulator::Emulator;
+
+// Candidates are only listed once the search has narrowed them down this far.
+const CANDIDATES_SHOWN: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Item {
+    Cheat(usize),
+    NewSearch,
+    Filter(Condition),
+    Candidate(usize),
+    Save,
+}
+
+// The cheats for the current ROM, to toggle with return and remove with
+// delete, followed by a memory search to find new ones: start a search, play
+// a little, then keep the addresses that changed the way the value did, until
+// few enough are left to pin one of them to its current value.
+#[derive(Default)]
+pub struct CheatMenu {
+    selected: usize,
+    // The value for the equal filter, changed with left and right.
+    value: u8,
+}
+
+impl CheatMenu {
+    fn items(&self, emulator: &Emulator) -> Vec<Item> {
+        let mut items: Vec<Item> = (0..emulator.cheats.get().len()).map(Item::Cheat).collect();
+        items.push(Item::NewSearch);
+        if let Some(ref search) = emulator.search {
+            let conditions = [Condition::Unchanged, Condition::Changed, Condition::Increased, Condition::Decreased, Condition::Equal(self.value)];
+            items.extend(conditions.iter().map(|&condition| Item::Filter(condition)));
+            if search.candidates().len() <= CANDIDATES_SHOWN {
+                items.extend(search.candidates().iter().map(|&address| Item::Candidate(address)));
+            }
+        }
+        items.push(Item::Save);
+        items
+    }
+
+    pub fn lines(&self, emulator: &Emulator) -> Vec<String> {
+        let memory = emulator.chip8.get_memory();
+        let line = |item| match item {
+            Item::Cheat(index) => {
+                let cheat = emulator.cheats.get()[index];
+                format!("[{}] {:03X} = {:02X}", if cheat.enabled { "X" } else { " " }, cheat.address, cheat.value)
+            }
+            Item::NewSearch => match emulator.search {
+                Some(ref search) => format!("new search: {} left", search.candidates().len()),
+                None => "new search".to_string(),
+            },
+            Item::Filter(Condition::Equal(value)) => format!("equal to < {:02X} >", value),
+            Item::Filter(condition) => format!("{:?}", condition).to_lowercase(),
+            Item::Candidate(address) => format!("pin {:03X} = {:02X}", address, memory[address]),
+            Item::Save => "save".to_string(),
+        };
+        self.items(emulator).into_iter().map(line).collect()
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    // Returns a message for the frontend to show, if the input produced one.
+    pub fn input(&mut self, name: &str, pressed: bool, emulator: &mut Emulator) -> Option<String> {
+        if !pressed {
+            return None;
+        }
+
+        let items = self.items(emulator);
+        let item = items[self.selected];
+        let mut message = None;
+        match (name, item) {
+            ("up", _) | ("pad_up", _) => self.selected = self.selected.saturating_sub(1),
+            ("down", _) | ("pad_down", _) => self.selected += 1,
+            ("left", Item::Filter(Condition::Equal(_))) | ("pad_left", Item::Filter(Condition::Equal(_))) => self.value = self.value.wrapping_sub(1),
+            ("right", Item::Filter(Condition::Equal(_))) | ("pad_right", Item::Filter(Condition::Equal(_))) => self.value = self.value.wrapping_add(1),
+            ("delete", Item::Cheat(index)) | ("backspace", Item::Cheat(index)) => emulator.cheats.remove(index),
+            ("return", _) | ("pad_button0", _) => message = self.choose(item, emulator),
+            _ => (),
+        }
+
+        // Removing cheats and narrowing the search shortens the menu.
+        self.selected = self.selected.min(self.items(emulator).len() - 1);
+        message
+    }
+
+    fn choose(&mut self, item: Item, emulator: &mut Emulator) -> Option<String> {
+        match item {
+            Item::Cheat(index) => emulator.cheats.toggle(index),
+            Item::NewSearch => emulator.search = Some(Search::new(emulator.chip8.get_memory())),
+            Item::Filter(condition) => {
+                if let Some(ref mut search) = emulator.search {
+                    search.filter(emulator.chip8.get_memory(), condition);
+                }
+            }
+            Item::Candidate(address) => {
+                let value = emulator.chip8.get_memory()[address];
+                emulator.cheats.add(address, value);
+            }
+            Item::Save => return Some(emulator.save_cheats()),
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chip8::{Bindings, Filter, Palette, Platform, Scheduler, Timing};
+
+    #[test]
+    fn search_and_pin() {
+        let quirks = Platform::default().quirks();
+        let scheduler = Scheduler::new(Timing::Instructions(500.0));
+        let mut emulator = Emulator::new(vec![0x00, 0xE0], "test".to_string(), quirks, scheduler, Bindings::default(), Palette::default(), Filter::None).unwrap();
+        let mut menu = CheatMenu::default();
+        assert_eq!(menu.lines(&emulator), ["new search", "save"]);
+
+        menu.input("return", true, &mut emulator);
+        emulator.chip8.set_memory(0x300, 5);
+        menu.input("down", true, &mut emulator);
+        menu.input("down", true, &mut emulator);
+        menu.input("return", true, &mut emulator);
+        assert_eq!(menu.lines(&emulator)[0], "new search: 1 left");
+        assert_eq!(menu.lines(&emulator)[6], "pin 300 = 05");
+
+        for _ in 0..4 {
+            menu.input("down", true, &mut emulator);
+        }
+        menu.input("return", true, &mut emulator);
+        assert_eq!(menu.lines(&emulator)[0], "[X] 300 = 05");
+        // The new cheat moved the selection onto the equal filter.
+        menu.input("left", true, &mut emulator);
+        assert_eq!(menu.lines(&emulator)[6], "equal to < FF >");
+        menu.input("up", true, &mut emulator);
+        menu.input("left", true, &mut emulator);
+        assert_eq!(menu.lines(&emulator)[6], "equal to < FF >");
+    }
+}
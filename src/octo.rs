@@ -0,0 +1,936 @@
+use std::collections::{HashMap, VecDeque};
+use std::f64::consts::{E, PI};
+
+// Programs start at 0x200 with a jump to `main`, and can be assembled up to
+// the end of XO-CHIP memory.
+const START: usize = 0x200;
+const MEMORY_SIZE: usize = 0x10000;
+// Guards against macros that expand into themselves.
+const MAX_EXPANSIONS: usize = 100_000;
+
+const RESERVED: [&str; 77] = [
+    ":=", "|=", "&=", "^=", "-=", "=-", "+=", ">>=", "<<=", "==", "!=", "<", ">", "<=", ">=", "key", "-key", "hex", "bighex", "random", "delay", ":",
+    ":next", ":unpack", ":breakpoint", ":monitor", ":alias", ":const", ":calc", ":byte", ":pointer", ":org", ":call", ":macro", ":assert", ";",
+    "return", "clear", "bcd", "save", "load", "saveflags", "loadflags", "buzzer", "pitch", "if", "then", "begin", "else", "end", "jump", "jump0",
+    "native", "sprite", "loop", "while", "again", "scroll-down", "scroll-up", "scroll-right", "scroll-left", "lores", "hires", "exit", "i", "audio",
+    "plane", "long", "{", "}", "(", ")", "-", "HERE", "PI", "E", "@",
+];
+
+const UNARY: [&str; 13] = ["-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs", "sqrt", "sign", "ceil", "floor"];
+const BINARY: [&str; 19] = ["-", "+", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", "==", "!=", ">=", ">"];
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+// Which part of the code a name used before its definition fills in.
+#[derive(Clone, Copy, Debug)]
+enum Field {
+    // The low 12 bits of an instruction.
+    Address,
+    // Two bytes, as after `i := long`.
+    Word,
+    // The low nibble of the second byte of an instruction, for `:unpack`.
+    HighNibble,
+    // The second byte of an instruction, for `:unpack`.
+    HighByte,
+    LowByte,
+}
+
+struct Reference {
+    at: usize,
+    field: Field,
+    name: String,
+    line: usize,
+}
+
+enum Target {
+    Known(usize),
+    Forward(String),
+}
+
+// Assembles Octo source code into a ROM to load at 0x200. Octo is the
+// assembly language of the Octo IDE, which cartridges hold their programs
+// in, for example:
+//
+//     : main
+//       i := ball
+//       loop
+//         sprite v0 v1 4
+//         v0 += 1
+//       again
+//     : ball 0x60 0xF0 0xF0 0x60
+//
+// Labels, constants, aliases, macros, `:calc` expressions, structured `if`,
+// `loop` and `while`, and the SCHIP and XO-CHIP instructions are supported,
+// `:stringmode` isn't.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut assembler = Assembler {
+        tokens: tokenize(source),
+        line: 1,
+        memory: vec![0; MEMORY_SIZE],
+        here: START,
+        end: START,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        macros: HashMap::new(),
+        expansions: 0,
+        references: Vec::new(),
+        loops: Vec::new(),
+        branches: Vec::new(),
+    };
+    assembler.assemble()
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let mut chars = line.chars().peekable();
+        loop {
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+            let mut text = String::new();
+            match chars.peek() {
+                None | Some('#') => break,
+                Some('"') => {
+                    text.push(chars.next().unwrap());
+                    for c in chars.by_ref() {
+                        text.push(c);
+                        if c == '"' {
+                            break;
+                        }
+                    }
+                }
+                _ => {
+                    while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                        text.push(c);
+                    }
+                }
+            }
+            tokens.push_back(Token { text, line: index + 1 });
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value } as f64)
+}
+
+fn register_index(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|digit| digit as u8),
+        _ => None,
+    }
+}
+
+fn unary(op: &str, value: f64) -> f64 {
+    match op {
+        "-" => -value,
+        "~" => !(value as i64) as f64,
+        "!" => f64::from(u8::from(value == 0.0)),
+        "sin" => value.sin(),
+        "cos" => value.cos(),
+        "tan" => value.tan(),
+        "exp" => value.exp(),
+        "log" => value.ln(),
+        "abs" => value.abs(),
+        "sqrt" => value.sqrt(),
+        "sign" => value.signum(),
+        "ceil" => value.ceil(),
+        _ => value.floor(),
+    }
+}
+
+fn binary(op: &str, left: f64, right: f64) -> f64 {
+    let (a, b) = (left as i64, right as i64);
+    let truth = |value: bool| f64::from(u8::from(value));
+    match op {
+        "-" => left - right,
+        "+" => left + right,
+        "*" => left * right,
+        "/" => left / right,
+        "%" => left % right,
+        "&" => (a & b) as f64,
+        "|" => (a | b) as f64,
+        "^" => (a ^ b) as f64,
+        "<<" => a.checked_shl(b as u32).unwrap_or(0) as f64,
+        ">>" => a.checked_shr(b as u32).unwrap_or(0) as f64,
+        "pow" => left.powf(right),
+        "min" => left.min(right),
+        "max" => left.max(right),
+        "<" => truth(left < right),
+        "<=" => truth(left <= right),
+        "==" => truth(left == right),
+        "!=" => truth(left != right),
+        ">=" => truth(left >= right),
+        _ => truth(left > right),
+    }
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    // Of the last token read, for errors.
+    line: usize,
+    memory: Vec<u8>,
+    here: usize,
+    // Just past the last byte assembled.
+    end: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, (Vec<String>, Vec<Token>)>,
+    expansions: usize,
+    references: Vec<Reference>,
+    // Open loops with their start and the `while` jumps out of them, and the
+    // jumps past open `if ... begin` blocks.
+    loops: Vec<(usize, Vec<usize>)>,
+    branches: Vec<usize>,
+}
+
+impl Assembler {
+    fn assemble(&mut self) -> Result<Vec<u8>, String> {
+        // Filled in with the jump to `main` at the end.
+        self.instruction(0x10, 0x00)?;
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+
+        if !self.loops.is_empty() {
+            return self.fail("a loop is missing its 'again'");
+        }
+        if !self.branches.is_empty() {
+            return self.fail("an 'if ... begin' is missing its 'end'");
+        }
+        let main = match self.labels.get("main") {
+            Some(&main) => main,
+            None => return Err("the program has no 'main' label".to_string()),
+        };
+        self.patch_jump(START, main)?;
+
+        for reference in &self.references {
+            let value = match self.labels.get(&reference.name).cloned().or_else(|| self.constants.get(&reference.name).map(|&value| value as usize)) {
+                Some(value) => value,
+                None => return Err(format!("line {}: undefined name '{}'", reference.line, reference.name)),
+            };
+            let at = reference.at;
+            match reference.field {
+                Field::Address if value > 0xFFF => return Err(format!("line {}: '{}' is beyond 0xFFF", reference.line, reference.name)),
+                Field::Address => {
+                    self.memory[at] |= (value >> 8) as u8 & 0x0F;
+                    self.memory[at + 1] = value as u8;
+                }
+                Field::Word => {
+                    self.memory[at] = (value >> 8) as u8;
+                    self.memory[at + 1] = value as u8;
+                }
+                Field::HighNibble => self.memory[at + 1] |= (value >> 8) as u8 & 0x0F,
+                Field::HighByte => self.memory[at + 1] = (value >> 8) as u8,
+                Field::LowByte => self.memory[at + 1] = value as u8,
+            }
+        }
+
+        Ok(self.memory[START..self.end].to_vec())
+    }
+
+    fn fail<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("line {}: {}", self.line, message))
+    }
+
+    fn next_token(&mut self) -> Result<Token, String> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token)
+            }
+            None => self.fail("unexpected end of the program"),
+        }
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        self.next_token().map(|token| token.text)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token != text {
+            return self.fail(&format!("expected '{}' but found '{}'", text, token));
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, byte: u8) -> Result<(), String> {
+        if self.here >= MEMORY_SIZE {
+            return self.fail("the program doesn't fit in memory");
+        }
+        self.memory[self.here] = byte;
+        self.here += 1;
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    fn instruction(&mut self, high: u8, low: u8) -> Result<(), String> {
+        self.byte(high)?;
+        self.byte(low)
+    }
+
+    fn patch_jump(&mut self, at: usize, target: usize) -> Result<(), String> {
+        if target > 0xFFF {
+            return self.fail(&format!("can't jump to 0x{:X}, beyond 0xFFF", target));
+        }
+        self.memory[at] = 0x10 | (target >> 8) as u8;
+        self.memory[at + 1] = target as u8;
+        Ok(())
+    }
+
+    fn is_identifier(&self, text: &str) -> bool {
+        !RESERVED.contains(&text) && parse_number(text).is_none() && register_index(text).is_none() && !text.starts_with('"')
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        let name = self.next()?;
+        if !self.is_identifier(&name) {
+            return self.fail(&format!("'{}' can't be used as a name", name));
+        }
+        Ok(name)
+    }
+
+    fn is_register(&self) -> bool {
+        self.peek().is_some_and(|token| self.register(token).is_some())
+    }
+
+    fn register(&self, name: &str) -> Option<u8> {
+        self.aliases.get(name).cloned().or_else(|| register_index(name))
+    }
+
+    fn next_register(&mut self) -> Result<u8, String> {
+        let name = self.next()?;
+        match self.register(&name) {
+            Some(register) => Ok(register),
+            None => self.fail(&format!("expected a register but found '{}'", name)),
+        }
+    }
+
+    fn known_value(&self, name: &str) -> Option<f64> {
+        parse_number(name)
+            .or_else(|| self.constants.get(name).cloned())
+            .or_else(|| self.labels.get(name).map(|&address| address as f64))
+    }
+
+    // A number, constant, label defined earlier or `{ expression }`.
+    fn value(&mut self) -> Result<i64, String> {
+        let token = self.next()?;
+        if token == "{" {
+            return self.calc_block().map(|value| value as i64);
+        }
+        match self.known_value(&token) {
+            Some(value) => Ok(value as i64),
+            None => self.fail(&format!("undefined name '{}'", token)),
+        }
+    }
+
+    fn value_in(&mut self, min: i64, max: i64) -> Result<i64, String> {
+        let value = self.value()?;
+        if value < min || value > max {
+            return self.fail(&format!("{} is out of range, expected {} to {}", value, min, max));
+        }
+        Ok(value)
+    }
+
+    // A byte, negative values counting down from 256.
+    fn short_value(&mut self) -> Result<u8, String> {
+        self.value_in(-128, 255).map(|value| value as u8)
+    }
+
+    fn tiny_value(&mut self) -> Result<u8, String> {
+        self.value_in(0, 15).map(|value| value as u8)
+    }
+
+    // An address, which may be a label defined further on.
+    fn target(&mut self) -> Result<Target, String> {
+        let token = self.next()?;
+        if token == "{" {
+            return self.calc_block().map(|value| Target::Known(value as usize));
+        }
+        match self.known_value(&token) {
+            Some(value) if value < 0.0 || value >= MEMORY_SIZE as f64 => self.fail(&format!("0x{:X} is beyond memory", value as i64)),
+            Some(value) => Ok(Target::Known(value as usize)),
+            None if self.is_identifier(&token) => Ok(Target::Forward(token)),
+            None => self.fail(&format!("expected an address but found '{}'", token)),
+        }
+    }
+
+    // The value of a target, or 0 with a reference to fill it in later.
+    fn refer(&mut self, target: &Target, at: usize, field: Field) -> usize {
+        match *target {
+            Target::Known(value) => value,
+            Target::Forward(ref name) => {
+                let line = self.line;
+                self.references.push(Reference { at, field, name: name.clone(), line });
+                0
+            }
+        }
+    }
+
+    // An instruction with a 12 bit address.
+    fn address_instruction(&mut self, op: u8) -> Result<(), String> {
+        let target = self.target()?;
+        let address = self.refer(&target, self.here, Field::Address);
+        if address > 0xFFF {
+            return self.fail(&format!("0x{:X} is beyond 0xFFF", address));
+        }
+        self.instruction(op << 4 | (address >> 8) as u8, address as u8)
+    }
+
+    fn calc_block(&mut self) -> Result<f64, String> {
+        let value = self.calc()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    // Operators have no precedence and group to the right, as in Octo.
+    fn calc(&mut self) -> Result<f64, String> {
+        let left = self.term()?;
+        let op = match self.peek() {
+            Some(op) if BINARY.contains(&op) => op.to_string(),
+            _ => return Ok(left),
+        };
+        self.next()?;
+        let right = self.calc()?;
+        Ok(binary(&op, left, right))
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let token = self.next()?;
+        match token.as_str() {
+            "(" => {
+                let value = self.calc()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(PI),
+            "E" => Ok(E),
+            "@" => {
+                let address = self.term()?;
+                Ok(self.memory.get(address as usize).map_or(0.0, |&byte| f64::from(byte)))
+            }
+            _ if UNARY.contains(&token.as_str()) => {
+                let value = self.term()?;
+                Ok(unary(&token, value))
+            }
+            _ => match self.known_value(&token) {
+                Some(value) => Ok(value),
+                None => self.fail(&format!("undefined name '{}'", token)),
+            },
+        }
+    }
+
+    fn define(&mut self, name: String, address: usize) -> Result<(), String> {
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return self.fail(&format!("'{}' is defined twice", name));
+        }
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        if let Some((params, body)) = self.macros.get(&token).cloned() {
+            return self.expand(params, body);
+        }
+        if let Some(x) = self.register(&token) {
+            return self.register_statement(x);
+        }
+
+        match token.as_str() {
+            ":" => {
+                let name = self.identifier()?;
+                let here = self.here;
+                self.define(name, here)?;
+            }
+            ":next" => {
+                let name = self.identifier()?;
+                let here = self.here;
+                self.define(name, here + 1)?;
+            }
+            ":alias" => {
+                let name = self.identifier()?;
+                let register = self.next_register()?;
+                self.aliases.insert(name, register);
+            }
+            ":const" => {
+                let name = self.identifier()?;
+                let value = self.value()?;
+                self.constants.insert(name, value as f64);
+            }
+            ":calc" => {
+                let name = self.identifier()?;
+                self.expect("{")?;
+                let value = self.calc_block()?;
+                self.constants.insert(name, value);
+            }
+            ":byte" => {
+                let byte = self.short_value()?;
+                self.byte(byte)?;
+            }
+            ":pointer" => {
+                let target = self.target()?;
+                let address = self.refer(&target, self.here, Field::Word);
+                self.instruction((address >> 8) as u8, address as u8)?;
+            }
+            ":org" => self.here = self.value_in(START as i64, MEMORY_SIZE as i64 - 1)? as usize,
+            ":unpack" => self.unpack()?,
+            ":macro" => self.define_macro()?,
+            ":assert" => {
+                let message = match self.peek() {
+                    Some(message) if message.starts_with('"') => Some(message.trim_matches('"').to_string()),
+                    _ => None,
+                };
+                if message.is_some() {
+                    self.next()?;
+                }
+                if self.value()? == 0 {
+                    return self.fail(&format!("assertion failed{}", message.map_or(String::new(), |message| format!(": {}", message))));
+                }
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            ":call" => self.address_instruction(0x2)?,
+            "clear" => self.instruction(0x00, 0xE0)?,
+            "return" | ";" => self.instruction(0x00, 0xEE)?,
+            "scroll-right" => self.instruction(0x00, 0xFB)?,
+            "scroll-left" => self.instruction(0x00, 0xFC)?,
+            "exit" => self.instruction(0x00, 0xFD)?,
+            "lores" => self.instruction(0x00, 0xFE)?,
+            "hires" => self.instruction(0x00, 0xFF)?,
+            "audio" => self.instruction(0xF0, 0x02)?,
+            "scroll-down" => {
+                let n = self.tiny_value()?;
+                self.instruction(0x00, 0xC0 | n)?;
+            }
+            "scroll-up" => {
+                let n = self.tiny_value()?;
+                self.instruction(0x00, 0xD0 | n)?;
+            }
+            "plane" => {
+                let n = self.value_in(0, 3)? as u8;
+                self.instruction(0xF0 | n, 0x01)?;
+            }
+            "bcd" | "saveflags" | "loadflags" => {
+                let x = self.next_register()?;
+                let low = match token.as_str() {
+                    "bcd" => 0x33,
+                    "saveflags" => 0x75,
+                    _ => 0x85,
+                };
+                self.instruction(0xF0 | x, low)?;
+            }
+            "save" | "load" => {
+                let x = self.next_register()?;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.next_register()?;
+                    let n = if token == "save" { 0x2 } else { 0x3 };
+                    self.instruction(0x50 | x, y << 4 | n)?;
+                } else {
+                    self.instruction(0xF0 | x, if token == "save" { 0x55 } else { 0x65 })?;
+                }
+            }
+            "sprite" => {
+                let x = self.next_register()?;
+                let y = self.next_register()?;
+                let n = self.tiny_value()?;
+                self.instruction(0xD0 | x, y << 4 | n)?;
+            }
+            "jump" => self.address_instruction(0x1)?,
+            "jump0" => self.address_instruction(0xB)?,
+            "native" => self.address_instruction(0x0)?,
+            "pitch" | "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.next_register()?;
+                let low = match token.as_str() {
+                    "pitch" => 0x3A,
+                    "delay" => 0x15,
+                    _ => 0x18,
+                };
+                self.instruction(0xF0 | x, low)?;
+            }
+            "i" => self.i_statement()?,
+            "if" => self.if_statement()?,
+            "else" => {
+                let branch = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => return self.fail("'else' without 'if ... begin'"),
+                };
+                let here = self.here;
+                self.instruction(0x10, 0x00)?;
+                self.patch_jump(branch, here + 2)?;
+                self.branches.push(here);
+            }
+            "end" => {
+                let branch = match self.branches.pop() {
+                    Some(branch) => branch,
+                    None => return self.fail("'end' without 'if ... begin'"),
+                };
+                let here = self.here;
+                self.patch_jump(branch, here)?;
+            }
+            "loop" => self.loops.push((self.here, Vec::new())),
+            "while" => {
+                if self.loops.is_empty() {
+                    return self.fail("'while' outside of a loop");
+                }
+                self.condition(true)?;
+                let here = self.here;
+                self.loops.last_mut().unwrap().1.push(here);
+                self.instruction(0x10, 0x00)?;
+            }
+            "again" => {
+                let (start, exits) = match self.loops.pop() {
+                    Some(open) => open,
+                    None => return self.fail("'again' without 'loop'"),
+                };
+                let here = self.here;
+                self.instruction(0x10, 0x00)?;
+                self.patch_jump(here, start)?;
+                for exit in exits {
+                    self.patch_jump(exit, here + 2)?;
+                }
+            }
+            _ => {
+                let constant = parse_number(&token).or_else(|| self.constants.get(&token).cloned());
+                match constant {
+                    // Numbers on their own are data.
+                    Some(value) if (-128.0..=255.0).contains(&value) => self.byte(value as i64 as u8)?,
+                    Some(value) => return self.fail(&format!("{} doesn't fit in a byte", value)),
+                    // Anything else is a subroutine to call.
+                    None if self.is_identifier(&token) => {
+                        self.tokens.push_front(Token { text: token, line: self.line });
+                        self.address_instruction(0x2)?;
+                    }
+                    None => return self.fail(&format!("unexpected '{}'", token)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), String> {
+        let op = self.next()?;
+        let low = match op.as_str() {
+            ":=" | "+=" | "-=" if !self.is_register() => {
+                let peeked = self.peek().map(str::to_string);
+                return match (op.as_str(), peeked.as_deref()) {
+                    (":=", Some("random")) => {
+                        self.next()?;
+                        let mask = self.short_value()?;
+                        self.instruction(0xC0 | x, mask)
+                    }
+                    (":=", Some("key")) => {
+                        self.next()?;
+                        self.instruction(0xF0 | x, 0x0A)
+                    }
+                    (":=", Some("delay")) => {
+                        self.next()?;
+                        self.instruction(0xF0 | x, 0x07)
+                    }
+                    (":=", _) => {
+                        let value = self.short_value()?;
+                        self.instruction(0x60 | x, value)
+                    }
+                    ("+=", _) => {
+                        let value = self.short_value()?;
+                        self.instruction(0x70 | x, value)
+                    }
+                    _ => {
+                        let value = self.short_value()?;
+                        self.instruction(0x70 | x, value.wrapping_neg())
+                    }
+                };
+            }
+            ":=" => 0x0,
+            "|=" => 0x1,
+            "&=" => 0x2,
+            "^=" => 0x3,
+            "+=" => 0x4,
+            "-=" => 0x5,
+            ">>=" => 0x6,
+            "=-" => 0x7,
+            "<<=" => 0xE,
+            _ => return self.fail(&format!("unknown operator '{}'", op)),
+        };
+        let y = self.next_register()?;
+        self.instruction(0x80 | x, y << 4 | low)
+    }
+
+    fn i_statement(&mut self) -> Result<(), String> {
+        let op = self.next()?;
+        match op.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") | Some("bighex") => {
+                    let big = self.next()? == "bighex";
+                    let x = self.next_register()?;
+                    self.instruction(0xF0 | x, if big { 0x30 } else { 0x29 })
+                }
+                Some("long") => {
+                    self.next()?;
+                    let target = self.target()?;
+                    self.instruction(0xF0, 0x00)?;
+                    let address = self.refer(&target, self.here, Field::Word);
+                    self.instruction((address >> 8) as u8, address as u8)
+                }
+                _ => self.address_instruction(0xA),
+            },
+            "+=" => {
+                let x = self.next_register()?;
+                self.instruction(0xF0 | x, 0x1E)
+            }
+            _ => self.fail(&format!("unknown operator 'i {}'", op)),
+        }
+    }
+
+    // `if ... then` skips the next statement unless the condition holds, and
+    // `if ... begin` jumps past the block to its `else` or `end`.
+    fn if_statement(&mut self) -> Result<(), String> {
+        let length = match self.tokens.get(1).map(|token| token.text.as_str()) {
+            Some("key") | Some("-key") => 2,
+            _ => 3,
+        };
+        match self.tokens.get(length).map(|token| token.text.clone()).as_deref() {
+            Some("then") => {
+                self.condition(false)?;
+                self.next()?;
+            }
+            Some("begin") => {
+                self.condition(true)?;
+                self.next()?;
+                let here = self.here;
+                self.branches.push(here);
+                self.instruction(0x10, 0x00)?;
+            }
+            _ => return self.fail("expected 'then' or 'begin' after the condition"),
+        }
+        Ok(())
+    }
+
+    // Emits what skips the next instruction unless the condition holds, or
+    // unless it doesn't when `negated`. Comparisons other than equality
+    // subtract into VF.
+    fn condition(&mut self, negated: bool) -> Result<(), String> {
+        let x = self.next_register()?;
+        let mut op = self.next()?;
+        if negated {
+            let opposite = match op.as_str() {
+                "==" => "!=",
+                "!=" => "==",
+                "key" => "-key",
+                "-key" => "key",
+                "<" => ">=",
+                ">" => "<=",
+                "<=" => ">",
+                ">=" => "<",
+                _ => return self.fail(&format!("unknown comparison '{}'", op)),
+            };
+            op = opposite.to_string();
+        }
+
+        match op.as_str() {
+            "==" | "!=" if self.is_register() => {
+                let y = self.next_register()?;
+                self.instruction(if op == "==" { 0x90 } else { 0x50 } | x, y << 4)
+            }
+            "==" | "!=" => {
+                let value = self.short_value()?;
+                self.instruction(if op == "==" { 0x40 } else { 0x30 } | x, value)
+            }
+            "key" => self.instruction(0xE0 | x, 0xA1),
+            "-key" => self.instruction(0xE0 | x, 0x9E),
+            "<" | ">" | "<=" | ">=" => {
+                if self.is_register() {
+                    let y = self.next_register()?;
+                    self.instruction(0x8F, y << 4)?;
+                } else {
+                    let value = self.short_value()?;
+                    self.instruction(0x6F, value)?;
+                }
+                // VF is 1 when VX >= the other value for `<` and `>=`, and when
+                // the other value >= VX for `>` and `<=`.
+                let subtract = if op == "<" || op == ">=" { 0x7 } else { 0x5 };
+                self.instruction(0x8F, x << 4 | subtract)?;
+                let skip = if op == "<" || op == ">" { 0x3F } else { 0x4F };
+                self.instruction(skip, 0x01)
+            }
+            _ => self.fail(&format!("unknown comparison '{}'", op)),
+        }
+    }
+
+    fn unpack(&mut self) -> Result<(), String> {
+        let long = self.peek() == Some("long");
+        let nibble = if long {
+            self.next()?;
+            0
+        } else {
+            self.tiny_value()?
+        };
+        let target = self.target()?;
+        let here = self.here;
+        let high = if long {
+            self.refer(&target, here, Field::HighByte) >> 8
+        } else {
+            self.refer(&target, here, Field::HighNibble) >> 8 & 0x0F
+        };
+        let address = self.refer(&target, here + 2, Field::LowByte);
+        self.instruction(0x60, (nibble << 4) | high as u8)?;
+        self.instruction(0x61, address as u8)
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.identifier()?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            params.push(token);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.next_token()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 1 => break,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, (params, body));
+        Ok(())
+    }
+
+    fn expand(&mut self, params: Vec<String>, body: Vec<Token>) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return self.fail("macros expand without end");
+        }
+        let mut args = HashMap::new();
+        for param in params {
+            let arg = self.next()?;
+            args.insert(param, arg);
+        }
+        for token in body.into_iter().rev() {
+            let text = args.get(&token.text).cloned().unwrap_or(token.text);
+            self.tokens.push_front(Token { text, line: self.line });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2).map(|pair| u16::from(pair[0]) << 8 | u16::from(*pair.get(1).unwrap_or(&0))).collect()
+    }
+
+    #[test]
+    fn instructions() {
+        let rom = assemble(
+            ": main
+               clear v3 := 0x12 v3 += 1 v3 -= 1 v4 := v3 v4 >>= v3 v4 =- v3 v5 := random 0xFF v5 := key
+               i := sprites i += v5 i := hex v5 sprite v1 v2 4 bcd v5 save v5 load v5 delay := v5
+               hires scroll-down 3 save v1 - v4 i := long 0x1234 ;
+             : sprites 0xF0 0x90",
+        )
+        .unwrap();
+        assert_eq!(
+            words(&rom),
+            [
+                0x1202, 0x00E0, 0x6312, 0x7301, 0x73FF, 0x8430, 0x8436, 0x8437, 0xC5FF, 0xF50A, 0xA230, 0xF51E, 0xF529, 0xD124, 0xF533, 0xF555,
+                0xF565, 0xF515, 0x00FF, 0x00C3, 0x5142, 0xF000, 0x1234, 0x00EE, 0xF090
+            ]
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        let rom = assemble(
+            ": main
+               loop
+                 if v0 == 3 then v1 := 1
+                 if v0 key begin v2 := 2 else v2 := 3 end
+                 if v0 < v1 then draw
+                 while v0 != 7
+                 v0 += 1
+               again
+             : draw ;",
+        )
+        .unwrap();
+        assert_eq!(
+            words(&rom),
+            [
+                0x1202, 0x4003, 0x6101, 0xE09E, 0x120E, 0x6202, 0x1210, 0x6203, 0x8F10, 0x8F07, 0x3F01, 0x2220, 0x4007, 0x1220, 0x7001, 0x1202,
+                0x00EE
+            ]
+        );
+    }
+
+    #[test]
+    fn names() {
+        let rom = assemble(
+            ":alias x v7
+             :const speed 3
+             :calc double { speed * 2 }
+             :macro step reg amount { reg += amount }
+             : main
+               step x double
+               :unpack 0xA data
+               x := speed
+             : data :pointer main :byte { 256 - 1 }",
+        )
+        .unwrap();
+        assert_eq!(words(&rom), [0x1202, 0x7706, 0x60A2, 0x610A, 0x6703, 0x0202, 0xFF00]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(assemble(": start ;"), Err("the program has no 'main' label".to_string()));
+        assert_eq!(assemble(": main\n  jump nowhere"), Err("line 2: undefined name 'nowhere'".to_string()));
+        assert_eq!(assemble(": main\n  v0 := 300"), Err("line 2: 300 is out of range, expected -128 to 255".to_string()));
+        assert_eq!(assemble(": main loop"), Err("line 1: a loop is missing its 'again'".to_string()));
+        assert!(assemble(": main :assert \"too big\" { 2 > 1 }").is_ok());
+        assert_eq!(assemble(": main :assert \"too big\" { 1 > 2 }"), Err("line 1: assertion failed: too big".to_string()));
+    }
+}
@@ -0,0 +1,106 @@
+use std::str::FromStr;
+
+pub type Colour = [f32; 4];
+
+// Colours for pixel values 0 to 3: the background, pixels set in the first
+// plane, in the second XO-CHIP plane and in both.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub colours: [Colour; 4],
+}
+
+// Named palettes, in the order the palette hotkey cycles through them.
+pub const NAMES: [&str; 6] = ["yellow", "green", "amber", "white", "lcd", "octo"];
+
+impl Palette {
+    pub fn named(name: &str) -> Option<Self> {
+        let colours = match name {
+            "yellow" => "000000 ffff00",
+            "green" => "001100 33ff66 1a8033 66ff99",
+            "amber" => "1a0f00 ffb000 805800 ffd080",
+            "white" => "000000 ffffff aaaaaa 555555",
+            "lcd" => "9bbc0f 0f380f 306230 8bac0f",
+            "octo" => "996600 ffcc00 ff6600 662200",
+            _ => return None,
+        };
+        Some(colours.parse().expect("named palettes should be valid"))
+    }
+
+    pub fn background(&self) -> Colour {
+        self.colours[0]
+    }
+
+    pub fn foreground(&self) -> Colour {
+        self.colours[1]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::named(NAMES[0]).unwrap()
+    }
+}
+
+// Parses a `rrggbb` colour, with an optional leading `#`.
+pub fn parse_colour(s: &str) -> Result<Colour, String> {
+    let hex = s.trim_start_matches('#');
+    let value = match hex.len() {
+        6 => u32::from_str_radix(hex, 16).map_err(|_| format!("invalid colour '{}'", s))?,
+        _ => return Err(format!("invalid colour '{}', expected rrggbb", s)),
+    };
+
+    let channel = |shift: u32| ((value >> shift) & 0xFF) as f32 / 255.0;
+    Ok([channel(16), channel(8), channel(0), 1.0])
+}
+
+// Parses either a palette name or two to four colours, background first,
+// separated by spaces or commas. Missing colours repeat the foreground.
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(palette) = Self::named(&s.trim().to_lowercase()) {
+            return Ok(palette);
+        }
+
+        let colours = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|colour| !colour.is_empty())
+            .map(parse_colour)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match colours[..] {
+            [background, foreground] => Ok(Palette {
+                colours: [background, foreground, foreground, foreground],
+            }),
+            [background, foreground, plane2] => Ok(Palette {
+                colours: [background, foreground, plane2, foreground],
+            }),
+            [background, foreground, plane2, both] => Ok(Palette {
+                colours: [background, foreground, plane2, both],
+            }),
+            _ => Err(format!(
+                "expected a palette name ({}) or two to four colours, found '{}'",
+                NAMES.join(", "),
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let palette: Palette = "#000000, ff8000".parse().unwrap();
+        assert_eq!(palette.background(), [0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(palette.foreground(), [1.0, 128.0 / 255.0, 0.0, 1.0]);
+        assert_eq!(palette.colours[3], palette.foreground());
+
+        assert_eq!("Amber".parse::<Palette>(), Ok(Palette::named("amber").unwrap()));
+        assert!("000000".parse::<Palette>().is_err());
+        assert!("000000 fffffg".parse::<Palette>().is_err());
+    }
+}
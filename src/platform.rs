@@ -0,0 +1,143 @@
+use std::str::FromStr;
+
+// Behaviour that differs between the interpreters a ROM may have been written
+// for. Each `Platform` comes with a matching set of defaults.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    // SCHIP 1.1 scrolls by high resolution pixels even in low resolution
+    // mode, so a low resolution scroll only moves half the logical distance.
+    pub lores_scroll_halved: bool,
+    // Only the starting coordinate of a sprite wraps around the screen, any
+    // part of the sprite beyond the edge is not drawn.
+    pub clip_sprites: bool,
+    // SCHIP 1.1 sets VF to the number of sprite rows that collided or were
+    // clipped at the bottom when drawing in high resolution mode.
+    pub collision_rows: bool,
+    // XO-CHIP has 64 KiB of memory rather than 4 KiB, which also allows for
+    // larger ROMs.
+    pub large_memory: bool,
+    // SHL and SHR shift VX in place rather than storing VY shifted in VX.
+    pub shift_vx: bool,
+    // `LD [I], Vx` and `LD Vx, [I]` leave I alone rather than advancing it
+    // past the registers.
+    pub load_store_keeps_i: bool,
+    // SCHIP 1.1 decodes `JP V0, xnn` as a jump to xnn plus VX.
+    pub jump_vx: bool,
+    // OR, AND and XOR clear VF, as on the VIP.
+    pub logic_resets_vf: bool,
+    // SCHIP 1.1 draws `DRW Vx, Vy, 0` in low resolution mode as a sprite 8
+    // pixels wide and 16 rows high, where XO-CHIP draws 16 by 16 as in high
+    // resolution.
+    pub lores_tall_sprites: bool,
+    // The VIP draws `DRW Vx, Vy, 0` as a sprite of no rows, so nothing at all,
+    // which takes precedence over `lores_tall_sprites`.
+    pub lores_empty_sprites: bool,
+}
+
+impl Quirks {
+    // Sets a quirk by name, as used on the command line and in configuration.
+    pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        match name {
+            "lores_scroll_halved" => self.lores_scroll_halved = value,
+            "clip_sprites" => self.clip_sprites = value,
+            "collision_rows" => self.collision_rows = value,
+            "large_memory" => self.large_memory = value,
+            "shift_vx" => self.shift_vx = value,
+            "load_store_keeps_i" => self.load_store_keeps_i = value,
+            "jump_vx" => self.jump_vx = value,
+            "logic_resets_vf" => self.logic_resets_vf = value,
+            "lores_tall_sprites" => self.lores_tall_sprites = value,
+            "lores_empty_sprites" => self.lores_empty_sprites = value,
+            _ => return Err(format!("unknown quirk '{}'", name)),
+        }
+        Ok(())
+    }
+
+    // Applies a `name`, `name=true` or `name=false` setting.
+    pub fn apply(&mut self, setting: &str) -> Result<(), String> {
+        let mut parts = setting.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let value = match parts.next().map(str::trim) {
+            None | Some("true") | Some("1") => true,
+            Some("false") | Some("0") => false,
+            Some(value) => return Err(format!("invalid value '{}' for quirk '{}'", value, name)),
+        };
+        self.set(name, value)
+    }
+
+    pub fn memory_size(&self) -> usize {
+        if self.large_memory {
+            0x10000
+        } else {
+            0x1000
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Platform {
+    // The original interpreter on the COSMAC VIP.
+    Chip8,
+    // SCHIP 1.1 on the HP 48, which also shifts VX in place and keeps I on
+    // loads and stores.
+    Schip,
+    // XO-CHIP as Octo runs it.
+    #[default]
+    XoChip,
+}
+
+impl Platform {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks {
+                lores_scroll_halved: false,
+                clip_sprites: true,
+                collision_rows: false,
+                large_memory: false,
+                shift_vx: false,
+                load_store_keeps_i: false,
+                jump_vx: false,
+                logic_resets_vf: true,
+                lores_tall_sprites: false,
+                lores_empty_sprites: true,
+            },
+            Platform::Schip => Quirks {
+                lores_scroll_halved: true,
+                clip_sprites: true,
+                collision_rows: true,
+                large_memory: false,
+                shift_vx: true,
+                load_store_keeps_i: true,
+                jump_vx: false,
+                logic_resets_vf: false,
+                lores_tall_sprites: true,
+                lores_empty_sprites: false,
+            },
+            Platform::XoChip => Quirks {
+                lores_scroll_halved: false,
+                clip_sprites: false,
+                collision_rows: false,
+                large_memory: true,
+                shift_vx: false,
+                load_store_keeps_i: false,
+                jump_vx: false,
+                logic_resets_vf: false,
+                lores_tall_sprites: false,
+                lores_empty_sprites: false,
+            },
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Platform::Chip8),
+            "schip" | "superchip" => Ok(Platform::Schip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(format!("unknown platform '{}', expected chip8, schip or xochip", s)),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use instruction::Instruction;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::mem::{self, Discriminant};
+
+// Instructions run and the COSMAC VIP machine cycles they took.
+#[derive(Clone, Copy, Default)]
+struct Cost {
+    instructions: u64,
+    cycles: u64,
+}
+
+impl Cost {
+    fn add(&mut self, other: Cost) {
+        self.instructions += other.instructions;
+        self.cycles += other.cycles;
+    }
+
+    fn since(self, start: Cost) -> Cost {
+        Cost { instructions: self.instructions - start.instructions, cycles: self.cycles - start.cycles }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Subroutine {
+    calls: u64,
+    cost: Cost,
+}
+
+// Counts where a ROM spends its time: how often each address and each kind
+// of instruction was run, and how many instructions each subroutine took
+// from its CALL to its RET, including those of the subroutines it called.
+// Machine cycles are counted alongside, and are what the lists are sorted by
+// when the ROM runs with VIP timing, where they are what a frame budgets.
+#[derive(Default)]
+pub struct Profiler {
+    by_cycles: bool,
+    total: Cost,
+    addresses: HashMap<u16, Cost>,
+    instructions: HashMap<Discriminant<Instruction>, (String, Cost)>,
+    subroutines: HashMap<u16, Subroutine>,
+    // The subroutines running, innermost last, with the total when each was
+    // called.
+    calls: Vec<(u16, Cost)>,
+}
+
+impl Profiler {
+    pub fn new(by_cycles: bool) -> Self {
+        Profiler { by_cycles, ..Profiler::default() }
+    }
+
+    // Records an instruction that just ran from `pc` and took `cycles`, see
+    // `Chip8::get_cycles`.
+    pub fn record(&mut self, pc: u16, instruction: &Instruction, cycles: u32) {
+        let cost = Cost { instructions: 1, cycles: u64::from(cycles) };
+        self.total.add(cost);
+        self.addresses.entry(pc).or_default().add(cost);
+        let kind = self.instructions.entry(mem::discriminant(instruction)).or_insert_with(|| (kind_name(instruction), Cost::default()));
+        kind.1.add(cost);
+
+        match *instruction {
+            Instruction::CALL { nnn } => self.calls.push((nnn, self.total)),
+            Instruction::RET => {
+                if let Some((address, start)) = self.calls.pop() {
+                    let subroutine = self.subroutines.entry(address).or_default();
+                    subroutine.calls += 1;
+                    subroutine.cost.add(self.total.since(start));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // The counts sorted by cost, addresses joined with their disassembly
+    // from `memory`. Subroutines that haven't returned yet count up to now.
+    // Percentages are of instructions, or of cycles when sorting by them.
+    pub fn report(&self, memory: &[u8]) -> String {
+        let by_cycles = self.by_cycles;
+        let key = |cost: &Cost| if by_cycles { cost.cycles } else { cost.instructions };
+        let percent = |cost: &Cost| 100.0 * key(cost) as f64 / key(&self.total).max(1) as f64;
+        let mut report = format!("{} instructions, {} cycles\n", self.total.instructions, self.total.cycles);
+
+        report += "\nAddresses\n    count      cycles        %  address  instruction\n";
+        for (address, cost) in by_cost(self.addresses.iter().map(|(&address, &cost)| (address, cost)).collect(), |cost| key(cost)) {
+            let instruction = Instruction::decode(memory, address as usize).map_or(String::new(), |instruction| instruction.to_string());
+            writeln!(report, "{:9} {:11} {:7.2}%  {:03X}      {}", cost.instructions, cost.cycles, percent(&cost), address, instruction).unwrap();
+        }
+
+        report += "\nInstructions\n    count      cycles        %  instruction\n";
+        for (name, cost) in by_cost(self.instructions.values().cloned().collect(), |cost| key(cost)) {
+            writeln!(report, "{:9} {:11} {:7.2}%  {}", cost.instructions, cost.cycles, percent(&cost), name).unwrap();
+        }
+
+        let mut subroutines = self.subroutines.clone();
+        for &(address, start) in &self.calls {
+            subroutines.entry(address).or_default().cost.add(self.total.since(start));
+        }
+        report += "\nSubroutines\n    calls  instructions      cycles        %  address\n";
+        for (address, subroutine) in by_cost(subroutines.into_iter().collect(), |subroutine| key(&subroutine.cost)) {
+            let cost = subroutine.cost;
+            writeln!(report, "{:9} {:13} {:11} {:7.2}%  {:03X}", subroutine.calls, cost.instructions, cost.cycles, percent(&cost), address).unwrap();
+        }
+        report
+    }
+}
+
+// The variant name, such as `DRW` or `LDxkk`.
+fn kind_name(instruction: &Instruction) -> String {
+    let name = format!("{:?}", instruction);
+    name.split(' ').next().unwrap_or_default().to_string()
+}
+
+// Most costly first, then by key.
+fn by_cost<K: Ord, V, F: Fn(&V) -> u64>(mut entries: Vec<(K, V)>, cost: F) -> Vec<(K, V)> {
+    entries.sort_by(|a, b| cost(&b.1).cmp(&cost(&a.1)).then_with(|| a.0.cmp(&b.0)));
+    entries
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chip8::Chip8;
+    use scheduler::{Scheduler, Timing};
+
+    // CALL 0x206; JP 0x202; LD V0, 1; RET
+    const ROM: [u8; 10] = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x60, 0x01, 0x00, 0xEE];
+
+    #[test]
+    fn report() {
+        let mut chip8 = Chip8::new(&ROM).unwrap();
+        chip8.set_cycle_counting(true);
+        let mut scheduler = Scheduler::new(Timing::Instructions(360.0));
+        let mut profiler = Profiler::default();
+        scheduler.run_frame_with(&mut chip8, |chip8, pc, instruction| {
+            profiler.record(pc, instruction, chip8.get_cycles());
+            true
+        });
+
+        let report = profiler.report(chip8.get_memory());
+        assert!(report.starts_with("6 instructions, 124 cycles\n"));
+        assert!(report.contains("        3          69   50.00%  202      JP 0x202\n"));
+        assert!(report.contains("        3          69   50.00%  JPnnn\n"));
+        assert!(report.contains("        1             2          29   33.33%  206\n"));
+    }
+
+    #[test]
+    fn running_subroutines_count() {
+        let mut profiler = Profiler::default();
+        profiler.record(0x200, &Instruction::CALL { nnn: 0x300 }, 13);
+        profiler.record(0x300, &Instruction::CLS, 24);
+        profiler.record(0x302, &Instruction::CLS, 24);
+        assert!(profiler.report(&[]).contains("        0             2          48   66.67%  300\n"));
+    }
+
+    // With VIP timing the costliest instruction comes first, however rarely
+    // it ran.
+    #[test]
+    fn by_cycles() {
+        let mut profiler = Profiler::new(true);
+        profiler.record(0x200, &Instruction::CLS, 24);
+        profiler.record(0x202, &Instruction::DRW { x: 0, y: 0, n: 1 }, 100);
+        profiler.record(0x200, &Instruction::CLS, 24);
+        let report = profiler.report(&[]);
+        assert!(report.contains("Addresses\n    count      cycles        %  address  instruction\n        1         100   67.57%  202"));
+    }
+}
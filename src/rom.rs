@@ -0,0 +1,118 @@
+use cartridge::Cartridge;
+use std::fs;
+use std::path::{Path, PathBuf};
+use zip::Archive;
+
+// Files that come with ROMs in archives but aren't ROMs themselves.
+const NOT_ROMS: [&str; 14] = ["txt", "md", "nfo", "diz", "doc", "htm", "html", "pdf", "png", "jpg", "jpeg", "gif", "json", "8o"];
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.iter().any(|other| extension.eq_ignore_ascii_case(other)))
+}
+
+pub fn is_archive(path: &Path) -> bool {
+    has_extension(path, &["zip"])
+}
+
+fn is_rom(name: &str) -> bool {
+    let path = Path::new(name);
+    let hidden = path.file_name().and_then(|name| name.to_str()).is_none_or(|name| name.starts_with('.'));
+    !name.ends_with('/') && !name.starts_with("__MACOSX/") && !hidden && !has_extension(path, &NOT_ROMS)
+}
+
+fn open_archive(path: &Path) -> Result<Archive, String> {
+    let data = fs::read(path).map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+    Archive::parse(data).map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+// The ROMs in the zip archive at `path`, as paths that `read` accepts.
+pub fn archive_entries(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let archive = open_archive(path)?;
+    let mut entries: Vec<PathBuf> = archive.names().into_iter().filter(|name| is_rom(name)).map(|name| path.join(name)).collect();
+    entries.sort();
+    Ok(entries)
+}
+
+// A ROM along with the options it was shared with, which only Octo
+// cartridges have.
+#[derive(Default)]
+pub struct Rom {
+    pub data: Vec<u8>,
+    pub hz: Option<f64>,
+    pub palette: Option<String>,
+    pub quirks: Vec<String>,
+}
+
+// Reads a ROM from a file, or from a file inside a zip archive when the path
+// continues past the archive, as in `games.zip/PONG.ch8`. The programs in
+// Octo cartridges are assembled.
+pub fn read(path: &Path) -> Result<Rom, String> {
+    if !path.exists() {
+        if let Some(archive) = path.ancestors().skip(1).find(|ancestor| ancestor.is_file() && is_archive(ancestor)) {
+            let name: Vec<_> = path.strip_prefix(archive).unwrap().iter().map(|part| part.to_string_lossy()).collect();
+            let data = open_archive(archive)?.read(&name.join("/")).map_err(|error| format!("{}: {}", archive.display(), error))?;
+            return Ok(Rom { data, ..Rom::default() });
+        }
+    }
+
+    let data = fs::read(path).map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+    if is_archive(path) {
+        return Err(format!("{} is an archive, pick one of the ROMs inside it", path.display()));
+    }
+    if has_extension(path, &["gif"]) {
+        let cartridge = Cartridge::decode(&data).map_err(|error| format!("{}: {}", path.display(), error))?;
+        return Ok(Rom {
+            data: cartridge.program,
+            hz: cartridge.hz,
+            palette: cartridge.palette,
+            quirks: cartridge.quirks,
+        });
+    }
+    Ok(Rom { data, ..Rom::default() })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chip8::Chip8;
+    use platform::Platform;
+
+    #[test]
+    fn roms_in_archives() {
+        assert!(is_rom("PONG.ch8"));
+        assert!(is_rom("games/BLITZ"));
+        assert!(!is_rom("games/"));
+        assert!(!is_rom("README.TXT"));
+        assert!(!is_rom("__MACOSX/PONG.ch8"));
+        assert!(!is_rom("games/.DS_Store"));
+    }
+
+    #[test]
+    fn cartridge() {
+        let rom = read(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/games/BOUNCE.gif"))).unwrap();
+        assert_eq!(rom.hz, Some(1200.0));
+        assert_eq!(rom.palette.as_deref(), Some("996600 FFCC00 FF6600 662200"));
+        assert_eq!(
+            rom.quirks,
+            ["clip_sprites=true", "shift_vx=false", "load_store_keeps_i=false", "jump_vx=false", "logic_resets_vf=true", "large_memory=false"]
+        );
+
+        let mut quirks = Platform::XoChip.quirks();
+        for quirk in &rom.quirks {
+            quirks.apply(quirk).unwrap();
+        }
+        let mut chip8 = Chip8::with_quirks(&rom.data, quirks).unwrap();
+        for _ in 0..10 {
+            for _ in 0..20 {
+                chip8.tick();
+            }
+            chip8.decrement_counter();
+        }
+        // The ball, having moved down and to the right.
+        let lit: Vec<_> = (0..32).flat_map(|y| (0..64).map(move |x| (x, y))).filter(|&(x, y)| chip8.get_display().get_pixel(x, y)).collect();
+        assert_eq!(lit.len(), 12);
+        assert!(lit[0].0 > 10 && lit[0].1 > 5);
+    }
+}
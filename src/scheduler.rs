@@ -0,0 +1,195 @@
+use chip8::{Chip8, State, VIP_CYCLES_PER_FRAME};
+use instruction::Instruction;
+
+pub const FRAMES_PER_SECOND: f64 = 60.0;
+const FRAME_TIME: f64 = 1.0 / FRAMES_PER_SECOND;
+// Never try to catch up on more than this many frames at once, times the
+// speed, so a stalled host (debugger, window drag) doesn't cause a burst of
+// emulation afterwards.
+const MAX_FRAMES_PER_UPDATE: u32 = 10;
+// The range the speed can be adjusted in while running.
+pub const MIN_SPEED: f64 = 1.0 / 16.0;
+pub const MAX_SPEED: f64 = 16.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timing {
+    // A flat number of instructions per second.
+    Instructions(f64),
+    // COSMAC VIP machine cycles per frame, see `Chip8::get_cycles`.
+    Vip,
+}
+
+pub struct Scheduler {
+    timing: Timing,
+    speed: f64,
+    frame_time: f64,
+    cycles: f64,
+}
+
+impl Scheduler {
+    pub fn new(timing: Timing) -> Self {
+        Scheduler {
+            timing,
+            speed: 1.0,
+            frame_time: 0.0,
+            cycles: 0.0,
+        }
+    }
+
+    pub fn get_speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    // Advances emulation by `dt` seconds of host time, running as many whole
+    // 60 Hz frames as have elapsed, times the speed. Returns the number of
+    // frames run.
+    pub fn update(&mut self, chip8: &mut Chip8, dt: f64) -> u32 {
+        self.update_with(chip8, dt, |_| ())
+    }
+
+    // Like `update`, calling `on_frame` after every frame that was run.
+    pub fn update_with<F: FnMut(&mut Chip8)>(&mut self, chip8: &mut Chip8, dt: f64, mut on_frame: F) -> u32 {
+        let frames = self.due_frames(dt);
+        for _ in 0..frames {
+            self.run_frame(chip8);
+            on_frame(chip8);
+        }
+        frames
+    }
+
+    // The number of frames to run after `dt` seconds of host time, for
+    // callers that run them themselves. Frames come faster or slower with
+    // the speed, and with them the timers most games pace themselves by.
+    pub fn due_frames(&mut self, dt: f64) -> u32 {
+        self.frame_time += dt * self.speed;
+        let frames = (self.frame_time / FRAME_TIME).floor();
+        self.frame_time -= frames * FRAME_TIME;
+        let max = (f64::from(MAX_FRAMES_PER_UPDATE) * self.speed.max(1.0)).ceil() as u32;
+        (frames as u32).min(max)
+    }
+
+    // Runs the instructions belonging to a single frame followed by one
+    // timer tick. Any fractional instruction or cycle is carried over, unless
+    // the program is left waiting for a key.
+    pub fn run_frame(&mut self, chip8: &mut Chip8) {
+        self.run_frame_with(chip8, |_, _, _| true);
+    }
+
+    // Like `run_frame`, calling `on_instruction` after every instruction with
+    // the address it was at. The frame ends early, dropping what is left of
+    // it, when that returns false.
+    pub fn run_frame_with<F: FnMut(&Chip8, u16, &Instruction) -> bool>(&mut self, chip8: &mut Chip8, mut on_instruction: F) {
+        chip8.process_key_events();
+
+        match self.timing {
+            Timing::Instructions(hz) => {
+                self.cycles += hz / FRAMES_PER_SECOND;
+                while self.cycles >= 1.0 {
+                    if chip8.get_state() != State::Running {
+                        self.cycles = 0.0;
+                        break;
+                    }
+                    let pc = chip8.get_pc();
+                    let instruction = chip8.tick();
+                    self.cycles -= 1.0;
+                    if !on_instruction(chip8, pc, &instruction) {
+                        self.cycles = 0.0;
+                        break;
+                    }
+                }
+            }
+            Timing::Vip => {
+                chip8.set_cycle_counting(true);
+                self.cycles += VIP_CYCLES_PER_FRAME as f64;
+                while self.cycles > 0.0 {
+                    if chip8.get_state() != State::Running {
+                        self.cycles = 0.0;
+                        break;
+                    }
+                    let pc = chip8.get_pc();
+                    let instruction = chip8.tick();
+                    if !on_instruction(chip8, pc, &instruction) {
+                        self.cycles = 0.0;
+                        break;
+                    }
+                    if instruction.waits_for_vblank() {
+                        // The rest of this frame is spent waiting for the
+                        // display interrupt, the drawing itself happens after.
+                        self.cycles = -(chip8.get_cycles() as f64);
+                        break;
+                    }
+                    self.cycles -= chip8.get_cycles() as f64;
+                }
+            }
+        }
+
+        chip8.decrement_counter();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // LD V0, 0xFF; LD DT, V0; JP 0x204
+    const ROM: [u8; 6] = [0x60, 0xFF, 0xF0, 0x15, 0x12, 0x04];
+
+    #[test]
+    fn update_runs_timers_at_60_hz() {
+        let mut chip8 = Chip8::new(&ROM).unwrap();
+        let mut scheduler = Scheduler::new(Timing::Instructions(500.0));
+
+        for _ in 0..105 {
+            scheduler.update(&mut chip8, 0.01);
+        }
+
+        assert_eq!(chip8.get_delay_timer(), 0xFF - 63);
+    }
+
+    #[test]
+    fn speed_scales_timers() {
+        let drained = |speed: f64| {
+            let mut chip8 = Chip8::new(&ROM).unwrap();
+            let mut scheduler = Scheduler::new(Timing::Instructions(500.0));
+            scheduler.set_speed(speed);
+            for _ in 0..32 {
+                scheduler.update(&mut chip8, 1.0 / 32.0);
+            }
+            0xFF - chip8.get_delay_timer()
+        };
+        assert_eq!(drained(1.0), 60);
+        assert_eq!(drained(4.0), 4 * 60);
+        assert_eq!(drained(0.25), 60 / 4);
+    }
+
+    #[test]
+    fn run_frame_carries_fractional_instructions() {
+        let mut chip8 = Chip8::new(&ROM).unwrap();
+        let mut scheduler = Scheduler::new(Timing::Instructions(90.0));
+
+        scheduler.run_frame(&mut chip8);
+        assert_eq!(chip8.get_pc(), 0x202);
+        scheduler.run_frame(&mut chip8);
+        assert_eq!(chip8.get_pc(), 0x204);
+        assert_eq!(chip8.get_delay_timer(), 0xFF - 1);
+    }
+
+    #[test]
+    fn run_frame_with_stops_early() {
+        let mut chip8 = Chip8::new(&ROM).unwrap();
+        let mut scheduler = Scheduler::new(Timing::Instructions(600.0));
+
+        let mut pcs = Vec::new();
+        scheduler.run_frame_with(&mut chip8, |_, pc, _| {
+            pcs.push(pc);
+            pcs.len() < 2
+        });
+        assert_eq!(pcs, [0x200, 0x202]);
+        scheduler.run_frame(&mut chip8);
+        assert_eq!(chip8.get_pc(), 0x204);
+    }
+}
@@ -0,0 +1,109 @@
+use display::{HEIGHT, WIDTH};
+use config;
+use filter::Phosphor;
+use image::{imageops, Rgba, RgbaImage};
+use palette::Palette;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Screenshots are scaled to the same size for either resolution.
+pub const SCREENSHOT_WIDTH: u32 = 512;
+
+// Fills a WIDTH x HEIGHT image with the filtered display, each low resolution
+// pixel covering 2x2 image pixels. The window frontend uploads this as its
+// texture.
+pub fn paint(phosphor: &Phosphor, palette: &Palette, image: &mut RgbaImage) {
+    let scale = WIDTH / phosphor.get_size().0;
+    let channel = |value: f32| (value * 255.0).round() as u8;
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let colour = phosphor.get_colour(palette, x as usize / scale, y as usize / scale);
+        *pixel = Rgba([channel(colour[0]), channel(colour[1]), channel(colour[2]), 255]);
+    }
+}
+
+pub fn render(phosphor: &Phosphor, palette: &Palette) -> RgbaImage {
+    let mut image = RgbaImage::new(WIDTH as u32, HEIGHT as u32);
+    paint(phosphor, palette, &mut image);
+
+    let scale = SCREENSHOT_WIDTH / WIDTH as u32;
+    imageops::resize(&image, WIDTH as u32 * scale, HEIGHT as u32 * scale, imageops::FilterType::Nearest)
+}
+
+// `screenshots` in the data directory.
+pub fn directory() -> Option<PathBuf> {
+    config::data_dir().map(|dir| dir.join("screenshots"))
+}
+
+// The most recent screenshot saved by `save` for `name`.
+pub fn latest(dir: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| Some((timestamp(entry.file_name().to_str()?, name)?, entry.path())))
+        .max()
+        .map(|(_, path)| path)
+}
+
+fn timestamp(file_name: &str, name: &str) -> Option<u64> {
+    file_name.strip_prefix(name)?.strip_prefix('-')?.strip_suffix(".png")?.parse().ok()
+}
+
+// Saves a screenshot as `<dir>/<name>-<unix time>.png`.
+pub fn save(phosphor: &Phosphor, palette: &Palette, dir: &Path, name: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let path = dir.join(format!("{}-{}.png", name, time));
+    render(phosphor, palette).save(&path)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use display::{Display, Resolution, PLANE_1, PLANE_2};
+    use filter::Filter;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp("BLITZ-1600000000.png", "BLITZ"), Some(1600000000));
+        assert_eq!(timestamp("BLITZ-1600000000.png", "BLIT"), None);
+        assert_eq!(timestamp("BLITZ-1600000000.jpg", "BLITZ"), None);
+        assert_eq!(timestamp("BLITZ-X.png", "BLITZ"), None);
+    }
+
+    #[test]
+    fn render_size() {
+        let mut display = Display::new();
+        let mut phosphor = Phosphor::new(Filter::None);
+        display.toggle(0, 0);
+        phosphor.update(&display, true);
+
+        let palette = Palette::default();
+        let image = render(&phosphor, &palette);
+        assert_eq!(image.dimensions(), (512, 256));
+        assert_eq!(image.get_pixel(7, 7)[0], (palette.foreground()[0] * 255.0).round() as u8);
+        assert_eq!(image.get_pixel(8, 8)[0], (palette.background()[0] * 255.0).round() as u8);
+
+        display.set_resolution(Resolution::High);
+        phosphor.update(&display, true);
+        assert_eq!(render(&phosphor, &palette).dimensions(), (512, 256));
+    }
+
+    // The third and fourth colours are for the second plane and for both.
+    #[test]
+    fn render_planes() {
+        let mut display = Display::new();
+        let mut phosphor = Phosphor::new(Filter::None);
+        display.toggle_plane(PLANE_2, 0, 0);
+        display.toggle_plane(PLANE_1, 1, 0);
+        display.toggle_plane(PLANE_2, 1, 0);
+        phosphor.update(&display, true);
+
+        let palette: Palette = "000000 ff0000 00ff00 0000ff".parse().unwrap();
+        let image = render(&phosphor, &palette);
+        assert_eq!(image.get_pixel(0, 0), &Rgba([0, 255, 0, 255]));
+        assert_eq!(image.get_pixel(8, 0), &Rgba([0, 0, 255, 255]));
+    }
+}
@@ -0,0 +1,195 @@
+use chip8::{Chip8, State, ROM_OFFSET};
+use font;
+use image::{Rgba, RgbaImage};
+use instruction::Instruction;
+use palette::{Colour, Palette};
+use scheduler::Scheduler;
+use std::collections::BTreeSet;
+
+// Sprite pixels in the sheet are this many image pixels wide, and labels
+// are drawn at half that.
+const SCALE: u32 = 4;
+const LABEL_SCALE: u32 = SCALE / 2;
+const COLUMNS: u32 = 8;
+const PADDING: u32 = 2 * SCALE;
+// How often a key is pressed during a traced run, so games waiting for one
+// move on.
+const KEY_FRAMES: u32 = 30;
+
+// Sprite data drawn from `address`, `width` pixels wide: 8 for DRW, which
+// takes one byte per row, and 16 for DRWH, which takes two, except for SCHIP
+// in low resolution where it takes one. The VIP draws no rows for DRWH.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Sprite {
+    pub address: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Sprite {
+    // `large_size` is the width and height of DRWH sprites, see
+    // `Chip8::large_sprite_size`.
+    fn drawn_by(instruction: &Instruction, address: usize, large_size: (usize, usize)) -> Option<Self> {
+        match *instruction {
+            Instruction::DRW { n, .. } => Some(Sprite { address, width: 8, height: n as usize }),
+            Instruction::DRWH { .. } => Some(Sprite { address, width: large_size.0, height: large_size.1 }),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.height * self.width / 8
+    }
+
+    fn pixel(&self, memory: &[u8], x: usize, y: usize) -> bool {
+        memory[self.address + y * self.width / 8 + x / 8] & (0x80 >> (x % 8)) != 0
+    }
+}
+
+// Finds sprites without running the ROM, by looking for an `LD I, nnn`
+// followed by a draw. I is only tracked along the program text, so a jump
+// in between is taken not to change it, and DRWH is taken to draw 16 by 16.
+pub fn scan(memory: &[u8]) -> Vec<Sprite> {
+    let mut sprites = BTreeSet::new();
+    let mut i = None;
+    let mut address = ROM_OFFSET;
+    while let Some(instruction) = Instruction::decode(memory, address) {
+        address += instruction.size() as usize;
+        match instruction {
+            Instruction::LDnnn { nnn } | Instruction::LDnnnn { nnnn: nnn } => i = Some(nnn as usize),
+            Instruction::ADDix { .. } | Instruction::LDfx { .. } | Instruction::LDhfx { .. } => i = None,
+            _ => sprites.extend(i.and_then(|i| Sprite::drawn_by(&instruction, i, (16, 16)))),
+        }
+    }
+    valid(sprites, memory)
+}
+
+// Finds the sprites drawn while running the ROM for a number of frames,
+// pressing every key in turn. The run ends early when the program halts, see
+// `Chip8::get_state`.
+pub fn trace(chip8: &mut Chip8, scheduler: &mut Scheduler, frames: u32) -> Vec<Sprite> {
+    let mut sprites = BTreeSet::new();
+    for frame in 0..frames {
+        if let State::Halted { .. } = chip8.get_state() {
+            break;
+        }
+        if frame % KEY_FRAMES == 0 {
+            let key = (frame / KEY_FRAMES % 16) as u8;
+            chip8.push_key_event(key, true);
+            chip8.push_key_event(key, false);
+        }
+        scheduler.run_frame_with(chip8, |chip8, _, instruction| {
+            // With both XO-CHIP planes selected, the second plane's sprite
+            // follows the first.
+            if let Some(sprite) = Sprite::drawn_by(instruction, chip8.get_i() as usize, chip8.large_sprite_size()) {
+                let planes = chip8.get_display().get_planes().count_ones() as usize;
+                sprites.extend((0..planes).map(|plane| Sprite { address: sprite.address + plane * sprite.size(), ..sprite }));
+            }
+            true
+        });
+    }
+    valid(sprites, chip8.get_memory())
+}
+
+// Leaves out empty sprites and those running past the end of memory.
+fn valid(sprites: BTreeSet<Sprite>, memory: &[u8]) -> Vec<Sprite> {
+    sprites.into_iter().filter(|sprite| sprite.height > 0 && sprite.address + sprite.size() <= memory.len()).collect()
+}
+
+// Draws the sprites in a grid on a sheet, each labelled with its address
+// and size, using the background and foreground of the palette.
+pub fn sheet(memory: &[u8], sprites: &[Sprite], palette: &Palette) -> RgbaImage {
+    let label_height = font::GLYPH_HEIGHT as u32 * LABEL_SCALE;
+    let cell_width = 16 * SCALE + PADDING;
+    let cell_height = 16 * SCALE + label_height + 2 * PADDING;
+    let columns = (sprites.len() as u32).clamp(1, COLUMNS);
+    let rows = (sprites.len() as u32).div_ceil(COLUMNS).max(1);
+
+    let mut image = RgbaImage::from_pixel(columns * cell_width + PADDING, rows * cell_height + PADDING, rgba(palette.background()));
+    let foreground = rgba(palette.foreground());
+    for (index, sprite) in sprites.iter().enumerate() {
+        let left = index as u32 % COLUMNS * cell_width + PADDING;
+        let top = index as u32 / COLUMNS * cell_height + PADDING;
+
+        let label = format!("{:03X} {}X{}", sprite.address, sprite.width, sprite.height);
+        font::layout(&label, |x, y| fill(&mut image, left + x as u32 * LABEL_SCALE, top + y as u32 * LABEL_SCALE, LABEL_SCALE, foreground));
+
+        let top = top + label_height + PADDING;
+        for y in 0..sprite.height {
+            for x in (0..sprite.width).filter(|&x| sprite.pixel(memory, x, y)) {
+                fill(&mut image, left + x as u32 * SCALE, top + y as u32 * SCALE, SCALE, foreground);
+            }
+        }
+    }
+    image
+}
+
+fn fill(image: &mut RgbaImage, left: u32, top: u32, size: u32, colour: Rgba<u8>) {
+    for y in top..top + size {
+        for x in left..(left + size).min(image.width()) {
+            image.put_pixel(x, y, colour);
+        }
+    }
+}
+
+fn rgba(colour: Colour) -> Rgba<u8> {
+    let channel = |value: f32| (value * 255.0).round() as u8;
+    Rgba([channel(colour[0]), channel(colour[1]), channel(colour[2]), 255])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use platform::Platform;
+    use scheduler::Timing;
+
+    // LD I, 0x20A; DRW V0, V0, 2; LD I, 0x20C; DRW V0, V0, 0; JP 0x208; then
+    // the sprite data.
+    const ROM: [u8; 12] = [0xA2, 0x0A, 0xD0, 0x02, 0xA2, 0x0C, 0xD0, 0x00, 0x12, 0x08, 0x81, 0x42];
+
+    #[test]
+    fn scan_finds_loads_followed_by_draws() {
+        let chip8 = Chip8::new(&ROM).unwrap();
+        let sprites = scan(chip8.get_memory());
+        assert_eq!(sprites, [Sprite { address: 0x20A, width: 8, height: 2 }, Sprite { address: 0x20C, width: 16, height: 16 }]);
+    }
+
+    #[test]
+    fn trace_records_draws() {
+        let mut chip8 = Chip8::new(&ROM).unwrap();
+        let mut scheduler = Scheduler::new(Timing::Instructions(600.0));
+        assert_eq!(trace(&mut chip8, &mut scheduler, 2), [Sprite { address: 0x20A, width: 8, height: 2 }, Sprite { address: 0x20C, width: 16, height: 16 }]);
+
+        // 0x0000 isn't an instruction the emulator runs.
+        let mut chip8 = Chip8::new(&[0xA2, 0x06, 0xD0, 0x01, 0x00, 0x00]).unwrap();
+        assert_eq!(trace(&mut chip8, &mut scheduler, 10), [Sprite { address: 0x206, width: 8, height: 1 }]);
+    }
+
+    // Moving I past the end of memory, storing and drawing there wraps
+    // around rather than ending the trace.
+    #[test]
+    fn trace_past_end_of_memory() {
+        // LD V0, 0xFF; ADD I, V0; LD [I], V1; DRW V0, V0, 15; JP 0x202
+        let mut quirks = Platform::XoChip.quirks();
+        quirks.apply("load_store_keeps_i").unwrap();
+        let mut chip8 = Chip8::with_quirks(&[0x60, 0xFF, 0xF0, 0x1E, 0xF1, 0x55, 0xD0, 0x0F, 0x12, 0x02], quirks).unwrap();
+        let mut scheduler = Scheduler::new(Timing::Instructions(600.0));
+        let sprites = trace(&mut chip8, &mut scheduler, 120);
+        assert!(sprites.contains(&Sprite { address: 0xFF00, width: 8, height: 15 }));
+        assert!(sprites.contains(&Sprite { address: 0x00FE, width: 8, height: 15 }));
+    }
+
+    #[test]
+    fn sheet_draws_sprites() {
+        let mut memory = vec![0; 0x210];
+        memory[0x200] = 0x81;
+        let sprite = Sprite { address: 0x200, width: 8, height: 1 };
+        let image = sheet(&memory, &[sprite], &Palette::default());
+        let top = PADDING + font::GLYPH_HEIGHT as u32 * LABEL_SCALE + PADDING;
+
+        assert_eq!(image.dimensions(), (16 * SCALE + 2 * PADDING, 16 * SCALE + font::GLYPH_HEIGHT as u32 * LABEL_SCALE + 3 * PADDING));
+        assert_eq!(image.get_pixel(PADDING, top), &Rgba([255, 255, 0, 255]));
+        assert_eq!(image.get_pixel(PADDING + SCALE, top), &Rgba([0, 0, 0, 255]));
+        assert_eq!(image.get_pixel(PADDING + 7 * SCALE, top), &Rgba([255, 255, 0, 255]));
+    }
+}
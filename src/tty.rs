@@ -0,0 +1,314 @@
+use chip8::palette::Colour;
+use chip8::{Chip8, Palette, Phosphor};
+use app::App;
+use gamepad::Gamepad;
+use launcher::{self, Launcher};
+use memory::{self, Mark, MemoryView};
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+use termion::color::{self, Bg, Fg, Rgb};
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::{clear, cursor, style};
+
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+// Terminals only report key presses, and repeat them while a key is held. A
+// key counts as released when no repeat arrives in time; the first repeat
+// takes a lot longer than the ones after it.
+const RELEASE_DELAY: Duration = Duration::from_millis(500);
+const REPEAT_DELAY: Duration = Duration::from_millis(100);
+
+// Runs the emulator in the terminal until Esc or Ctrl-C is pressed. Every
+// character cell shows two pixels using half blocks, or 2x4 pixels with
+// braille characters.
+pub fn run(app: &mut App, braille: bool) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock().into_raw_mode()?;
+    write!(stdout, "{}{}", clear::All, cursor::Hide)?;
+
+    let result = run_loop(app, &mut stdout, braille);
+
+    write!(stdout, "{}{}\r\n", style::Reset, cursor::Show)?;
+    stdout.flush()?;
+    result
+}
+
+fn run_loop<W: Write>(app: &mut App, out: &mut W, braille: bool) -> io::Result<()> {
+    let mut keys = termion::async_stdin().keys();
+    let mut held: HashMap<String, Instant> = HashMap::new();
+    let mut status = String::new();
+    let mut drawn = None;
+    let mut last_update = Instant::now();
+    let mut gamepad = Gamepad::open();
+
+    loop {
+        let now = Instant::now();
+
+        for key in keys.by_ref() {
+            let name = match key? {
+                Key::Esc | Key::Ctrl('c') => return Ok(()),
+                key => match key_name(key) {
+                    Some(name) => name,
+                    None => continue,
+                },
+            };
+
+            if let Some(deadline) = held.get_mut(&name) {
+                *deadline = now + REPEAT_DELAY;
+                continue;
+            }
+
+            if let Some(message) = app.input(&name, true) {
+                status = message;
+            }
+            held.insert(name, now + RELEASE_DELAY);
+        }
+
+        // Controllers report releases, unlike the terminal.
+        for (name, pressed) in gamepad.poll() {
+            if let Some(message) = app.input(&name, pressed) {
+                status = message;
+            }
+        }
+
+        let released: Vec<String> = held.iter().filter(|&(_, &deadline)| deadline <= now).map(|(name, _)| name.clone()).collect();
+        for name in released {
+            held.remove(&name);
+            if let Some(message) = app.input(&name, false) {
+                status = message;
+            }
+        }
+
+        if let Some(instruction) = app.update((now - last_update).as_secs_f64()) {
+            status = format!("{:?}", instruction);
+        }
+        if let Some(halt) = app.take_halt() {
+            status = halt;
+        }
+        // Shows the latest of the frame's code writes and how many came
+        // before it.
+        let writes = app.take_code_writes();
+        match writes.split_last() {
+            Some((write, [])) => status = write.to_string(),
+            Some((write, earlier)) => status = format!("{} (and {} more)", write, earlier.len()),
+            None => (),
+        }
+        last_update = now;
+
+        // Only redraw when the picture, the launcher, the cheat menu or the
+        // status line changed.
+        let palette = app.palette();
+        let selected = app.launcher.as_ref().map(Launcher::selected);
+        let menu = match (&app.menu, &app.emulator) {
+            (Some(menu), Some(emulator)) => Some((menu.lines(emulator), menu.selected())),
+            _ => None,
+        };
+        let mode = match (&app.launcher, &app.emulator) {
+            (Some(_), _) | (None, None) => "launcher".to_string(),
+            (None, Some(_)) if menu.is_some() => "cheats".to_string(),
+            (None, Some(_)) if app.memory.as_ref().is_some_and(MemoryView::editing) => "edit".to_string(),
+            (None, Some(_)) if app.memory.is_some() => "memory".to_string(),
+            (None, Some(emulator)) => emulator.indicator().unwrap_or_else(|| "run".to_string()).to_lowercase(),
+        };
+        // Memory changes without anything showing on the display.
+        let changed = app.emulator.as_mut().is_some_and(|emulator| emulator.phosphor.take_changed()) || app.memory.is_some();
+        let current = (palette, status.clone(), mode.clone(), selected, menu);
+        if !changed && drawn.as_ref() == Some(&current) {
+            sleep_until(now + FRAME_TIME);
+            continue;
+        }
+
+        let mut frame = String::new();
+        match (&app.launcher, &current.4, &app.emulator) {
+            (Some(launcher), _, _) => render_launcher(&mut frame, launcher),
+            (None, Some((lines, selected)), _) => render_menu(&mut frame, lines, *selected),
+            (None, None, Some(emulator)) if app.memory.is_some() => render_memory(&mut frame, app.memory.as_ref().unwrap(), &emulator.chip8),
+            (None, None, Some(emulator)) if braille => render_braille(&mut frame, &emulator.phosphor, palette),
+            (None, None, Some(emulator)) => render_half_blocks(&mut frame, &emulator.phosphor, palette),
+            (None, None, None) => (),
+        }
+        write!(frame, "{}{}{} {}{}", style::Reset, clear::UntilNewline, mode, status, clear::AfterCursor).unwrap();
+
+        write!(out, "{}{}", cursor::Goto(1, 1), frame)?;
+        out.flush()?;
+        drawn = Some(current);
+
+        sleep_until(now + FRAME_TIME);
+    }
+}
+
+fn sleep_until(deadline: Instant) {
+    if let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        thread::sleep(remaining);
+    }
+}
+
+// Lists as many ROMs as fit in the terminal, with details of the selected
+// one underneath.
+fn render_launcher(frame: &mut String, launcher: &Launcher) {
+    let rows = termion::terminal_size().map_or(20, |(_, height)| height as usize).saturating_sub(4).max(1);
+
+    write!(frame, "Pick a ROM with the arrow keys and return{}\r\n", clear::UntilNewline).unwrap();
+    for index in launcher.visible(rows) {
+        let title = &launcher.entries()[index].title;
+        if index == launcher.selected() {
+            write!(frame, "{}> {}{}", style::Invert, title, style::Reset).unwrap();
+        } else {
+            write!(frame, "  {}", title).unwrap();
+        }
+        write!(frame, "{}\r\n", clear::UntilNewline).unwrap();
+    }
+    write!(frame, "{}{}\r\n", launcher.selected_entry().details.join(", "), clear::UntilNewline).unwrap();
+}
+
+fn render_menu(frame: &mut String, lines: &[String], selected: usize) {
+    let rows = termion::terminal_size().map_or(20, |(_, height)| height as usize).saturating_sub(3).max(1);
+
+    write!(frame, "Cheats: return picks, delete removes, left and right change the value{}\r\n", clear::UntilNewline).unwrap();
+    for index in launcher::visible(selected, lines.len(), rows) {
+        if index == selected {
+            write!(frame, "{}> {}{}", style::Invert, lines[index], style::Reset).unwrap();
+        } else {
+            write!(frame, "  {}", lines[index]).unwrap();
+        }
+        write!(frame, "{}\r\n", clear::UntilNewline).unwrap();
+    }
+}
+
+// The hex view of memory with the sprite previews to its right.
+fn render_memory(frame: &mut String, view: &MemoryView, chip8: &Chip8) {
+    let rows = termion::terminal_size().map_or(20, |(_, height)| height as usize).saturating_sub(3).max(1);
+    let (row, count) = view.rows(chip8);
+    let sprite = view.sprite(chip8);
+    let wide_sprite = view.wide_sprite(chip8);
+    let pixels = |bits: u16, width: usize| (0..width).map(|x| if bits & (0x8000 >> x) != 0 { '█' } else { '·' }).collect::<String>();
+
+    write!(
+        frame,
+        "{:04X}  PC {:03X}  I {:03X}, home and end jump to them, hex digits edit{}\r\n",
+        view.cursor(),
+        chip8.get_pc(),
+        chip8.get_i(),
+        clear::UntilNewline
+    )
+    .unwrap();
+    for (line, row) in launcher::visible(row, count, rows).enumerate() {
+        let address = row * memory::ROW_SIZE;
+        write!(frame, "{:04X} ", address).unwrap();
+        for address in address..address + memory::ROW_SIZE {
+            let text = view.byte_text(chip8, address);
+            match view.mark(chip8, address) {
+                Mark::None => write!(frame, " {}", text),
+                Mark::Cursor => write!(frame, " {}{}{}", style::Invert, text, style::Reset),
+                Mark::Pc => write!(frame, " {}{}{}", Fg(color::LightGreen), text, style::Reset),
+                Mark::I => write!(frame, " {}{}{}", Fg(color::LightCyan), text, style::Reset),
+                Mark::Written => write!(frame, " {}{}{}", Fg(color::LightRed), text, style::Reset),
+            }
+            .unwrap();
+        }
+        if let Some(&bits) = sprite.get(line) {
+            write!(frame, "   {}", pixels(bits, 8)).unwrap();
+        } else if line < wide_sprite.len() {
+            frame.push_str("           ");
+        }
+        if let Some(&bits) = wide_sprite.get(line) {
+            write!(frame, "   {}", pixels(bits, 16)).unwrap();
+        }
+        write!(frame, "{}\r\n", clear::UntilNewline).unwrap();
+    }
+}
+
+fn render_half_blocks(frame: &mut String, phosphor: &Phosphor, palette: Palette) {
+    let (width, height) = phosphor.get_size();
+
+    for y in (0..height).step_by(2) {
+        let mut last = None;
+        for x in 0..width {
+            let colours = (rgb(phosphor.get_colour(&palette, x, y)), rgb(phosphor.get_colour(&palette, x, y + 1)));
+            if last != Some(colours) {
+                write!(frame, "{}{}", Fg(colours.0), Bg(colours.1)).unwrap();
+                last = Some(colours);
+            }
+            frame.push('▀');
+        }
+        write!(frame, "{}\r\n", style::Reset).unwrap();
+    }
+}
+
+// Braille dots are either on or off, so filtered pixels count as lit while
+// they are at least half as bright as the foreground.
+fn render_braille(frame: &mut String, phosphor: &Phosphor, palette: Palette) {
+    // Dot bits for each pixel of a 2x4 braille cell, in column major order.
+    const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+    let (width, height) = phosphor.get_size();
+
+    for y in (0..height).step_by(4) {
+        write!(frame, "{}{}", Fg(rgb(palette.foreground())), Bg(rgb(palette.background()))).unwrap();
+        for x in (0..width).step_by(2) {
+            let mut dots = 0;
+            for (dx, column) in DOTS.iter().enumerate() {
+                for (dy, dot) in column.iter().enumerate() {
+                    if phosphor.get_intensity(x + dx, y + dy) >= 0.5 {
+                        dots |= dot;
+                    }
+                }
+            }
+            frame.push(std::char::from_u32(0x2800 + dots).unwrap());
+        }
+        write!(frame, "{}\r\n", style::Reset).unwrap();
+    }
+}
+
+fn rgb(colour: Colour) -> Rgb {
+    let channel = |value: f32| (value * 255.0).round() as u8;
+    Rgb(channel(colour[0]), channel(colour[1]), channel(colour[2]))
+}
+
+// Names keys like the window frontend does, so the same `[keys]` bindings
+// work in both.
+fn key_name(key: Key) -> Option<String> {
+    let name = match key {
+        Key::Char(' ') => "space".to_string(),
+        Key::Char('\t') => "tab".to_string(),
+        Key::Char('\n') => "return".to_string(),
+        Key::Char('-') => "minus".to_string(),
+        Key::Char('=') => "equals".to_string(),
+        Key::Char('`') => "backquote".to_string(),
+        Key::Char(c) => c.to_lowercase().to_string(),
+        Key::F(n) => format!("f{}", n),
+        Key::Left => "left".to_string(),
+        Key::Right => "right".to_string(),
+        Key::Up => "up".to_string(),
+        Key::Down => "down".to_string(),
+        Key::Backspace => "backspace".to_string(),
+        Key::Home => "home".to_string(),
+        Key::End => "end".to_string(),
+        Key::PageUp => "pageup".to_string(),
+        Key::PageDown => "pagedown".to_string(),
+        Key::Delete => "delete".to_string(),
+        Key::Insert => "insert".to_string(),
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chip8::bindings::DEFAULT_BINDINGS;
+
+    #[test]
+    fn default_bindings_reachable() {
+        let keys = (' '..='~').map(Key::Char).chain(['\t', '\n'].iter().cloned().map(Key::Char)).chain((1..=12).map(Key::F));
+        let keys = keys.chain(vec![Key::Left, Key::Right, Key::Up, Key::Down, Key::Backspace, Key::Home, Key::End, Key::PageUp, Key::PageDown]);
+        let names: Vec<String> = keys.filter_map(key_name).collect();
+        // Controllers are read apart from the terminal.
+        for &(name, action) in DEFAULT_BINDINGS.iter().filter(|&&(name, _)| !name.starts_with("pad_")) {
+            assert!(names.iter().any(|other| other == name), "{:?} is bound to '{}', which the terminal can't report", action, name);
+        }
+    }
+}
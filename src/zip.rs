@@ -0,0 +1,170 @@
+use inflate::inflate_bytes;
+
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+struct Entry {
+    name: String,
+    method: u16,
+    offset: usize,
+    compressed_size: usize,
+    size: usize,
+}
+
+// A zip archive read from memory. Only what ROM collections need is
+// supported: stored and deflated files, no encryption, no zip64.
+pub struct Archive {
+    data: Vec<u8>,
+    entries: Vec<Entry>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(|bytes| u16::from(bytes[0]) | u16::from(bytes[1]) << 8)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from(u16_at(data, offset)?) | u32::from(u16_at(data, offset + 2)?) << 16)
+}
+
+impl Archive {
+    pub fn parse(data: Vec<u8>) -> Result<Self, String> {
+        let entries = Self::central_directory(&data).ok_or_else(|| "not a valid zip archive".to_string())?;
+        Ok(Archive { data, entries })
+    }
+
+    fn central_directory(data: &[u8]) -> Option<Vec<Entry>> {
+        // The end of central directory record is followed by a comment of
+        // up to 64 KiB, so search backwards for its signature.
+        let last = data.len().checked_sub(END_OF_CENTRAL_DIRECTORY_SIZE)?;
+        let end = (last.saturating_sub(0xFFFF)..=last).rev().find(|&offset| u32_at(data, offset) == Some(END_OF_CENTRAL_DIRECTORY))?;
+
+        let count = u16_at(data, end + 10)? as usize;
+        let mut offset = u32_at(data, end + 16)? as usize;
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            if u32_at(data, offset)? != CENTRAL_HEADER {
+                return None;
+            }
+            let name_length = u16_at(data, offset + 28)? as usize;
+            let name = data.get(offset + 46..offset + 46 + name_length)?;
+            entries.push(Entry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: u16_at(data, offset + 10)?,
+                offset: u32_at(data, offset + 42)? as usize,
+                compressed_size: u32_at(data, offset + 20)? as usize,
+                size: u32_at(data, offset + 24)? as usize,
+            });
+            offset += 46 + name_length + u16_at(data, offset + 30)? as usize + u16_at(data, offset + 32)? as usize;
+        }
+        Some(entries)
+    }
+
+    // The names of the files in the archive, leaving out directories.
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.iter().map(|entry| entry.name.as_str()).filter(|name| !name.ends_with('/')).collect()
+    }
+
+    pub fn read(&self, name: &str) -> Result<Vec<u8>, String> {
+        let entry = self.entries.iter().find(|entry| entry.name == name).ok_or_else(|| format!("no file '{}' in the archive", name))?;
+        let invalid = || format!("invalid zip entry for '{}'", name);
+
+        // The local header repeats the name and has an extra field of its
+        // own, so the data only starts after those.
+        let header = entry.offset;
+        if u32_at(&self.data, header) != Some(LOCAL_HEADER) {
+            return Err(invalid());
+        }
+        let name_length = u16_at(&self.data, header + 26).ok_or_else(invalid)? as usize;
+        let extra_length = u16_at(&self.data, header + 28).ok_or_else(invalid)? as usize;
+        let start = header + 30 + name_length + extra_length;
+        let compressed = self.data.get(start..start + entry.compressed_size).ok_or_else(invalid)?;
+
+        let data = match entry.method {
+            STORED => compressed.to_vec(),
+            DEFLATED => inflate_bytes(compressed).map_err(|error| format!("could not inflate '{}': {}", name, error))?,
+            method => return Err(format!("unsupported compression method {} for '{}'", method, name)),
+        };
+        if data.len() != entry.size {
+            return Err(invalid());
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Builds an archive from (name, method, data as stored, size) entries.
+    fn archive(files: &[(&str, u16, &[u8], usize)]) -> Vec<u8> {
+        fn push_u16(data: &mut Vec<u8>, value: usize) {
+            data.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+        }
+        fn push_u32(data: &mut Vec<u8>, value: usize) {
+            push_u16(data, value & 0xFFFF);
+            push_u16(data, value >> 16);
+        }
+
+        let mut data = Vec::new();
+        let mut central = Vec::new();
+        for &(name, method, stored, size) in files {
+            let offset = data.len();
+            push_u32(&mut data, LOCAL_HEADER as usize);
+            data.extend_from_slice(&[20, 0, 0, 0]);
+            push_u16(&mut data, method as usize);
+            data.extend_from_slice(&[0; 8]);
+            push_u32(&mut data, stored.len());
+            push_u32(&mut data, size);
+            push_u16(&mut data, name.len());
+            push_u16(&mut data, 0);
+            data.extend_from_slice(name.as_bytes());
+            data.extend_from_slice(stored);
+
+            push_u32(&mut central, CENTRAL_HEADER as usize);
+            central.extend_from_slice(&[20, 3, 20, 0, 0, 0]);
+            push_u16(&mut central, method as usize);
+            central.extend_from_slice(&[0; 8]);
+            push_u32(&mut central, stored.len());
+            push_u32(&mut central, size);
+            push_u16(&mut central, name.len());
+            central.extend_from_slice(&[0; 12]);
+            push_u32(&mut central, offset);
+            central.extend_from_slice(name.as_bytes());
+        }
+
+        let central_offset = data.len();
+        data.extend_from_slice(&central);
+        push_u32(&mut data, END_OF_CENTRAL_DIRECTORY as usize);
+        data.extend_from_slice(&[0; 4]);
+        push_u16(&mut data, files.len());
+        push_u16(&mut data, files.len());
+        push_u32(&mut data, central.len());
+        push_u32(&mut data, central_offset);
+        push_u16(&mut data, 0);
+        data
+    }
+
+    #[test]
+    fn read() {
+        // Sixteen bytes of 00 E0 deflated.
+        let deflated = [0x63, 0x78, 0xC0, 0x80, 0x02, 0x01];
+        let data = archive(&[("games/", STORED, b"", 0), ("games/PONG.ch8", DEFLATED, &deflated, 16), ("README.txt", STORED, b"hi", 2)]);
+        let archive = Archive::parse(data).unwrap();
+
+        assert_eq!(archive.names(), vec!["games/PONG.ch8", "README.txt"]);
+        assert_eq!(archive.read("games/PONG.ch8").unwrap(), [0x00, 0xE0].repeat(8));
+        assert_eq!(archive.read("README.txt").unwrap(), b"hi");
+        assert!(archive.read("PONG.ch8").is_err());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(Archive::parse(b"PK not really".to_vec()).is_err());
+        assert!(Archive::parse(Vec::new()).is_err());
+    }
+}